@@ -0,0 +1,42 @@
+//! Blocking waits for the `std` feature.
+//!
+//! All `Once`s share a single mutex and condition variable. Threads that wait for a closure
+//! to complete sleep on the condition variable, and every closure that completes, fails or
+//! panics wakes all of them, such that each one checks whether its own `Once` is done.
+//! Threads that have to count their iterations, for a budget or the watchdog, wait for
+//! `SLICE_MS` at most per iteration.
+
+use std::sync::{StaticMutex, MUTEX_INIT, StaticCondvar, CONDVAR_INIT};
+use std::time::Duration;
+use core::atomic::{AtomicUint, Ordering};
+
+use once::RUNNING;
+
+static LOCK: StaticMutex = MUTEX_INIT;
+static CONDVAR: StaticCondvar = CONDVAR_INIT;
+
+/// The maximal duration of a single wait, in milliseconds.
+const SLICE_MS: i64 = 1;
+
+/// Blocks until `state` has moved on from `RUNNING`, or for `SLICE_MS` at most if `sliced`
+/// is set. May return early, so the caller has to check the state again.
+pub fn wait(state: &AtomicUint, sliced: bool) {
+    let guard = LOCK.lock();
+    // `notify_all` is called with the lock held after `state` has changed, so the change
+    // can't slip in between these checks and the waits
+    if sliced {
+        if state.load(Ordering::SeqCst) == RUNNING {
+            CONDVAR.wait_timeout(&guard, Duration::milliseconds(SLICE_MS));
+        }
+    } else {
+        while state.load(Ordering::SeqCst) == RUNNING {
+            CONDVAR.wait(&guard);
+        }
+    }
+}
+
+/// Wakes all threads blocked in `wait`, after a `Once` has left the `RUNNING` state.
+pub fn notify_all() {
+    let _guard = LOCK.lock();
+    CONDVAR.notify_all();
+}
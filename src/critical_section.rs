@@ -0,0 +1,54 @@
+//! Hooks that run the initialization of lazy statics inside of a critical section.
+//!
+//! With the cargo feature `critical_section`, every initialization of a lazy static enters a
+//! user defined critical section before it claims the static and leaves it once the value
+//! is published, e.g. to keep interrupt handlers from running in between. The critical
+//! section has to be configured exactly once in the final binary, through
+//! `lazy_static_critical_section!`.
+
+use core::ops::Drop;
+
+/// A critical section implemented by the user, e.g. by disabling interrupts.
+pub trait CriticalSection {
+    /// Enters the critical section and returns a token that describes the state before, e.g.
+    /// whether interrupts were enabled.
+    fn acquire(&self) -> uint;
+
+    /// Leaves the critical section entered by the `acquire` call that returned `token`.
+    fn release(&self, token: uint);
+}
+
+/// Keeps the configured critical section entered for as long as it lives.
+#[doc(hidden)]
+pub struct Guard {
+    #[allow(dead_code)]
+    token: uint,
+}
+
+impl Guard {
+    #[cfg(feature = "critical_section")]
+    #[inline(always)]
+    pub fn acquire() -> Guard {
+        Guard { token: unsafe { lazy_static_core_cs_acquire() } }
+    }
+
+    #[cfg(not(feature = "critical_section"))]
+    #[inline(always)]
+    pub fn acquire() -> Guard {
+        Guard { token: 0 }
+    }
+}
+
+#[cfg(feature = "critical_section")]
+impl Drop for Guard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        unsafe { lazy_static_core_cs_release(self.token) }
+    }
+}
+
+#[cfg(feature = "critical_section")]
+extern "Rust" {
+    fn lazy_static_core_cs_acquire() -> uint;
+    fn lazy_static_core_cs_release(token: uint);
+}
@@ -0,0 +1,54 @@
+//! Sleeping waits through raw futex system calls, for the `futex` feature.
+//!
+//! Threads that wait for a closure to complete sleep in `FUTEX_WAIT` on the state word of
+//! the `Once`, and the thread that publishes the outcome wakes them through `FUTEX_WAKE`.
+//! Threads that have to count their iterations, for a budget or the watchdog, sleep for
+//! `SLICE_NS` at most per iteration.
+//! The system calls are made directly, without libc, so this only supports x86_64 Linux.
+
+use core::atomic::AtomicUint;
+
+use once::RUNNING;
+
+const SYS_FUTEX: uint = 202;
+const FUTEX_WAIT_PRIVATE: uint = 0 | 128;
+const FUTEX_WAKE_PRIVATE: uint = 1 | 128;
+const WAKE_ALL: uint = 0x7fffffff;
+
+/// The maximal duration of a single sleep, in nanoseconds.
+const SLICE_NS: i64 = 1_000_000;
+
+/// The relative timeout of `FUTEX_WAIT`, laid out like `struct timespec`.
+#[repr(C)]
+struct Timespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+/// Sleeps until `state` is woken through `wake_all`, or for `SLICE_NS` at most if `sliced` is
+/// set, unless it has moved on from `RUNNING` already. May return early, so the caller has
+/// to check the state again.
+pub fn wait(state: &AtomicUint, sliced: bool) {
+    let slice = Timespec { tv_sec: 0, tv_nsec: SLICE_NS };
+    let timeout = if sliced { &slice as *const Timespec } else { 0 as *const Timespec };
+    // the kernel compares the 32 bit word at the address of `state`, which holds its low
+    // half on this little endian target, and all states fit into it
+    unsafe { futex(state, FUTEX_WAIT_PRIVATE, RUNNING, timeout) };
+}
+
+/// Wakes all threads sleeping in `wait` on `state`.
+pub fn wake_all(state: &AtomicUint) {
+    unsafe { futex(state, FUTEX_WAKE_PRIVATE, WAKE_ALL, 0 as *const Timespec) };
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+unsafe fn futex(state: &AtomicUint, op: uint, val: uint, timeout: *const Timespec) -> uint {
+    let ret: uint;
+    asm!("syscall"
+         : "={rax}"(ret)
+         : "{rax}"(SYS_FUTEX), "{rdi}"(state as *const AtomicUint), "{rsi}"(op),
+           "{rdx}"(val), "{r10}"(timeout)
+         : "rcx", "r11", "memory"
+         : "volatile");
+    ret
+}
@@ -0,0 +1,72 @@
+//! A lazily evaluated value that can be used without the `lazy_static_core!` macro.
+
+use core::ops::{Deref, Fn};
+use core::option::{Option, Some, None};
+use core::result::Result;
+
+use InProgress;
+use once_cell::OnceCell;
+use wait::{WaitStrategy, DefaultStrategy};
+
+/// Describes a `Lazy` in panic messages.
+const WHAT: &'static str = "lazy value";
+
+/// A value that is computed by calling `init` on first access.
+///
+/// Like the statics declared with `lazy_static_core!`, `init` is called at most once, even if
+/// several threads access the value at the same time, and accesses after a panic of `init`
+/// panic, too. A `Lazy` can be a struct field, created through `Lazy::new`, or a `static`,
+/// created through the `lazy!` macro:
+///
+/// ```ignore
+/// static NUMBERS: Lazy<Vec<uint>> = lazy!(make_numbers);
+/// ```
+pub struct Lazy<T, F = fn() -> T> {
+    #[doc(hidden)]
+    pub cell: OnceCell<T>,
+    #[doc(hidden)]
+    pub init: F,
+}
+
+impl<T, F: Fn() -> T> Lazy<T, F> {
+    /// Creates a `Lazy` that computes its value through `init`.
+    pub fn new(init: F) -> Lazy<T, F> {
+        Lazy { cell: OnceCell::new(), init: init }
+    }
+
+    /// Returns the value, calling `init` first if that has not happened yet.
+    pub fn force(&self) -> &T {
+        // waiting never fails
+        self.force_with(WHAT, Some(&DefaultStrategy)).ok().unwrap()
+    }
+
+    /// Like `force`, but returns `Err(InProgress)` instead of waiting if another thread is
+    /// calling `init`.
+    pub fn try_force(&self) -> Result<&T, InProgress> {
+        self.force_with(WHAT, None::<&DefaultStrategy>)
+    }
+
+    /// Returns the value if it has been computed already, without calling `init`.
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+
+    /// Returns `true` if `init` has panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.cell.is_poisoned()
+    }
+
+    /// Like `force`, but describes the value as `what` in panic messages and waits according
+    /// to `strategy`, or not at all if that is `None`.
+    #[doc(hidden)]
+    pub fn force_with<W: WaitStrategy>(&self, what: &'static str, strategy: Option<&W>)
+                                       -> Result<&T, InProgress> {
+        self.cell.get_or_init_with(what, strategy, || (self.init)())
+    }
+}
+
+impl<T, F: Fn() -> T> Deref<T> for Lazy<T, F> {
+    fn deref<'a>(&'a self) -> &'a T {
+        self.force()
+    }
+}
@@ -1,6 +1,4 @@
 /*!
-DON'T USE! Seems to have a bug (tests panic sometimes at access of statics)...
-
 A macro for declaring lazily evaluated statics that doesn't depend on the standard library.
 
 Using this macro, it is possible to have `static`s that require code to be
@@ -16,6 +14,12 @@ lazy_static_core! {
     [pub] static ref NAME_2: TYPE_2 = EXPR_2;
     ...
     [pub] static ref NAME_N: TYPE_N = EXPR_N;
+    [pub] try static ref NAME: Result<TYPE, ERROR> = EXPR;
+    [pub] retry static ref NAME: TYPE = EXPR;
+    [pub] static ref NAME: TYPE = EXPR with STRATEGY;
+    [pub] static ref NAME: TYPE;
+    [pub] static ref mut NAME: TYPE = EXPR;
+    [#[ATTR] ...] [pub] static ref NAME: TYPE = EXPR;
 }
 ```
 
@@ -23,12 +27,146 @@ lazy_static_core! {
 
 For a given `static ref NAME: TYPE = EXPR;`, the macro generates a
 unique type that implements `Deref<TYPE>` and stores it in a static with name `NAME`.
+The type also implements the `LazyStatic<TYPE>` trait, which allows to force or to inspect
+the static without dereferencing it, and to write code that is generic over lazy statics.
 
 On first deref, `EXPR` gets evaluated and stored internally, such that all further derefs
-can return a reference to the same object.
+can return a reference to the same object. If several threads deref the static at the same
+time, `EXPR` is still evaluated only once and the other threads wait until the value is
+available.
+
+If evaluating `EXPR` panics, the static is poisoned: all further derefs panic with the
+message `lazy static NAME was poisoned` instead of evaluating `EXPR` again. Whether this has
+happened can be queried without panicking through the `is_poisoned` method of the generated
+type, i.e. `NAME.is_poisoned()`.
+
+For a given `try static ref NAME: Result<TYPE, ERROR> = EXPR;`, `EXPR` has to evaluate
+to a `Result<TYPE, ERROR>`. The generated type still implements `Deref<TYPE>`, but it also
+has a method `try_get` that returns a `Result<&'static TYPE, &'static ERROR>`. Like the
+value, an error is evaluated only once and then kept forever. A deref of a static whose
+initializer failed panics with the message of the error, which therefore has to implement
+`Show`.
+
+For a given `retry static ref NAME: TYPE = EXPR;`, `EXPR` has to evaluate to an
+`Option<TYPE>`, where `None` means that the value can not be computed yet, for example
+because a device is not present yet. In that case the static stays uninitialized and the
+next access evaluates `EXPR` again, until it returns `Some`; from then on `EXPR` is never
+evaluated again. The generated type has a method `try_get` that returns an
+`Option<&'static TYPE>`, while a deref of a static that is not ready panics.
+
+A `static ref NAME: TYPE;` without an initializer is set at runtime instead, e.g. to values
+passed to the entry point of a kernel, through `NAME::init(value)`. This returns
+`Err(value)` if the static has been set already. A deref before `init` panics with the
+message `lazy static NAME was dereferenced before it was initialized`, while `NAME.try_get()`
+returns `None` in that case.
+
+Initialization can be forced without dereferencing the static through
+`lazy_static_core::initialize(&NAME)`, or for several statics at once through
+`initialize_all!(NAME_1, NAME_2, ...)`. The value of a static can be read only if it has
+been computed already through `lazy_static_core::get(&NAME)`, which returns an
+`Option<&'static TYPE>` and never evaluates `EXPR` or waits for another thread. Likewise,
+`lazy_static_core::try_deref(&NAME)` behaves like a deref, but returns `Err(InProgress)`
+instead of waiting while another thread evaluates `EXPR`, e.g. for use in interrupt
+handlers that may have interrupted the initialization.
+
+If the initializing thread may hang, `lazy_static_core::deref_timeout(&NAME, BUDGET)`
+waits only until `BUDGET` is exhausted, e.g. `timeout::Iterations(1000)` or a
+`timeout::Deadline` of a user supplied `timeout::Clock`, and returns `Err(InProgress)` then.
+Until then, it waits like a deref does, with the wait strategy of the static.
+With the cargo feature `watchdog`, every thread that keeps waiting reports the static it is
+stuck on to a hook configured through `lazy_static_watchdog!(ITERATIONS, FUNCTION)`, which
+is called every `ITERATIONS` wait iterations. Threads that sleep because of the `std` or
+`futex` feature wake up at least once per millisecond while they wait with a budget or the
+`watchdog` feature is enabled, and each wakeup counts as an iteration. Otherwise, they sleep
+until the initializer has finished.
+
+To find out in which order lazy statics are initialized and which ones read each other in
+their `EXPR`, the cargo feature `trace` records every access to a lazy static during the
+initialization of another one. `lazy_static_core::trace::write_dot(&mut OUT)` writes the
+resulting dependency graph in the Graphviz DOT format to any `core::fmt::FormatWriter`.
+Recording an access never waits for other threads, so an access that finds the graph
+locked for too long is not recorded. Only the end of a recorded initialization may wait
+briefly until another core has finished recording. Initializations that run on several threads at the same time are
+only told apart if the current thread can be identified, see below.
+
+A thread that finds another thread evaluating `EXPR` waits according to a
+`lazy_static_core::wait::WaitStrategy`. Built in are `Spin`, which busy waits, `Backoff`,
+which busy waits for exponentially growing periods, and `Hook`, which calls a user supplied
+function, e.g. to halt the processor or to yield to a scheduler. A strategy is selected for a
+single static by appending `with STRATEGY` to any of the forms above, all other statics use
+`wait::DefaultStrategy`. That is `Spin`, unless the cargo feature `wait_backoff` or
+`wait_hook` selects another strategy for the whole program. The function called with
+`wait_hook` is configured exactly once in the final binary through
+`lazy_static_wait_hook!(FUNCTION)`.
+
+With the cargo feature `critical_section`, the initialization of every static runs inside
+of a critical section implemented by the user, e.g. one that disables interrupts, such that
+an interrupt handler can not re-enter a static while the interrupted code initializes it.
+The critical section is a type implementing `critical_section::CriticalSection`, which is
+configured exactly once in the final binary through `lazy_static_critical_section!(EXPR)`.
+
+On targets without atomic compare and swap, the cargo feature `no_cas` claims statics with
+plain atomic loads and stores inside of that critical section instead. It implies
+`critical_section` and requires a critical section that excludes all other code that may
+access lazy statics, e.g. one that disables interrupts on a single core system. As a side
+effect, a recursive initialization can be detected in this configuration, so it panics with
+the message `lazy static NAME was initialized recursively` instead of waiting forever.
+
+For hosted programs, the cargo feature `std` links the standard library and makes threads
+that wait for another thread's initializer block on a condition variable instead of running
+their wait strategy, such that they don't burn CPU time while an expensive `EXPR` runs.
+The syntax of `lazy_static_core!` is the same with and without it, and the crate stays
+`#![no_std]` by default.
+
+For `#![no_std]` programs on x86_64 Linux, the cargo feature `futex` makes waiting threads
+sleep in `futex` system calls on the state of the static instead, which are made directly,
+without libc. Every completed initialization then wakes the threads sleeping on it. If both
+`std` and `futex` are enabled, `std` takes precedence.
+
+The same lazy evaluation is available without the macro through the generic type
+`lazy_static_core::Lazy<T, F = fn() -> T>`, which calls `F` on first access. A `Lazy` can be
+a struct field, created through `Lazy::new(f)`, or a static, created through the `lazy!`
+macro, e.g. `static NAME: Lazy<TYPE> = lazy!(FUNCTION);`.
+
+`Lazy` is built on two more primitives that are exported on their own. `Once` runs a closure
+exactly once through `call_once`, and `OnceCell<T>` is a cell that is written at most once,
+either through `set`, which fails if the cell is written already, or through
+`get_or_init`. Statics of both are created through `ONCE_INIT` and `once_cell!()`.
+
+For values that are not `Sync`, such as `Cell`s or `RefCell`s, the `unsync` module contains
+single threaded versions of `Lazy` and `OnceCell` with the same API. They carry no atomics
+and are not `Sync` themselves, so they can be used in locals and struct fields only.
+
+Every declaration can be preceded by attributes and doc comments. They are applied to the
+generated type, the static and all of their impls, such that doc comments show up in
+rustdoc and `#[cfg(...)]` removes the declaration as a whole.
+
+A declaration is either private or `pub`, which is applied to both the generated type and
+the static. Restricted visibilities such as `pub(crate)` don't exist in the language this
+crate targets; a static that should be visible in its whole crate but not outside of it
+has to be declared `pub` inside of a private module.
+
+For mutable globals, the crate contains the spin locks `Mutex<T>` and `RwLock<T>`, which
+wait with `wait::DefaultStrategy` and are created in static initializers through `mutex!` and
+`rwlock!`. A `static ref mut NAME: TYPE = EXPR;` wraps the value of `EXPR` in a `Mutex`, i.e.
+`NAME` derefs to a `Mutex<TYPE>` and can be locked through `NAME.lock()`.
 
 Like regular `static mut`s, this macro only works for types that fulfill the `Sync`
-trait.
+trait. The type of a `static ref mut` only has to fulfill `Send`, as the `Mutex` makes it
+`Sync`.
+
+`EXPR` must not deref the static it initializes, neither directly nor through other lazy
+statics. If the current thread can be identified, such a recursive initialization panics
+with the message `lazy static NAME was initialized recursively`. That is the case with the
+cargo feature `std`, and with the cargo feature `thread_id`, for which the final binary
+provides an id of the current thread exactly once through `lazy_static_thread_id!(EXPR)`.
+The id must differ between all threads that can be suspended inside of an initializer, so
+the index of the current core only qualifies on systems without preemption besides
+interrupts. An interrupt handler counts as the thread it interrupted, so it panics, too, if
+it derefs a static whose initialization it interrupted, while `try_deref` returns
+`Err(InProgress)` in that case. Otherwise, a recursive initialization can not be told
+apart from another thread that is currently evaluating `EXPR`, so the deref waits for
+itself forever (except with the `no_cas` feature, see above).
 
 # Example
 
@@ -38,11 +176,43 @@ Using the macro:
 #![feature(phase)]
 
 extern crate core; //required
-#[phase(plugin)]
+#[phase(plugin, link)]
 extern crate lazy_static_core;
 
 use std::collections::HashMap;
 
+# // the hooks that some features require from the final binary
+# #[cfg(feature = "critical_section")]
+# mod critical_section {
+#     use lazy_static_core::critical_section::CriticalSection;
+#
+#     // the example runs on a single thread, which needs no exclusion
+#     struct Nothing;
+#
+#     impl CriticalSection for Nothing {
+#         fn acquire(&self) -> uint { 0 }
+#         fn release(&self, _: uint) {}
+#     }
+#
+#     lazy_static_critical_section!(Nothing);
+# }
+# #[cfg(feature = "wait_hook")]
+# mod wait_hook {
+#     fn spin(_: uint) {}
+#
+#     lazy_static_wait_hook!(spin);
+# }
+# #[cfg(feature = "watchdog")]
+# mod watchdog {
+#     fn ignore(_: &'static str, _: uint) {}
+#
+#     lazy_static_watchdog!(0, ignore);
+# }
+# #[cfg(feature = "thread_id")]
+# mod thread_id {
+#     // the example runs on a single thread
+#     lazy_static_thread_id!(0);
+# }
 lazy_static_core! {
     static ref HASHMAP: HashMap<uint, &'static str> = {
         let mut m = HashMap::new();
@@ -66,10 +236,25 @@ fn main() {
 
 # Implementation details
 
-The `Deref` implementation uses a hidden `static mut` that is guarded by a atomic check
-using `core::atomic::AtomicBool`. All lazily evaluated values are currently
-put in a heap allocated box, due to the Rust language currently not providing any way to
-define uninitialized `static mut` values.
+The `Deref` implementation uses a hidden `static` of type `Lazy<TYPE>`, whose value is
+guarded by a state machine stored in a `core::atomic::AtomicUint`. The state moves from uninitialized over running
+to complete, or to poisoned if `EXPR` panics; only the thread that wins the transition to
+running evaluates `EXPR`, all others wait until the state is complete or poisoned.
+
+All operations on the state are implemented by the `Once` type of this crate, the
+generated code only calls into it. The atomics backend is therefore chosen when this
+crate is compiled, through cargo features such as `no_cas`, and never shows in the syntax
+of `lazy_static_core!`. The `portable-atomic` crate is not available for the toolchain this
+crate targets, so there is no backend built on it; targets without compare and swap are
+supported through `no_cas` instead.
+
+The generated code refers to items of this crate, so it has to be linked in addition
+to loading its macros, using `#[phase(plugin, link)]`.
+
+The lazily evaluated value is stored inline in the `OnceCell` of the `Lazy`, as a
+`core::cell::UnsafeCell<Option<TYPE>>` that starts out as `None` and is written exactly once.
+No heap allocation takes place, so the macro can be used before an allocator exists or
+inside of one.
 
 */
 
@@ -77,56 +262,588 @@ define uninitialized `static mut` values.
 
 #![crate_type = "dylib"]
 
-#![feature(macro_rules)]
+#![feature(asm, default_type_params, macro_rules, phase, thread_local, unboxed_closures,
+           unsafe_destructor)]
+
+#[phase(plugin, link)] extern crate core;
+#[cfg(any(test, feature = "std"))] extern crate std;
+
+use core::option::Option;
+use core::result::Result;
+
+use timeout::Budget;
+
+pub use lazy::Lazy;
+pub use once::{Once, ONCE_INIT};
+pub use once_cell::OnceCell;
+pub use mutex::{Mutex, MutexGuard};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+pub mod once;
+pub mod wait;
+pub mod critical_section;
+pub mod unsync;
+pub mod timeout;
+pub mod trace;
+mod lazy;
+mod once_cell;
+mod mutex;
+mod rwlock;
+mod thread;
+#[cfg(feature = "std")]
+mod blocking;
+#[cfg(all(feature = "futex", not(feature = "std")))]
+mod futex;
+
+/// Implemented by all types generated by `lazy_static_core!`, such that code can be generic
+/// over lazy statics of a given type, e.g. by taking a `&'static LazyStatic<T>`.
+pub trait LazyStatic<T> {
+    /// Returns the value, evaluating the initializer first if that has not happened yet.
+    ///
+    /// This panics in the same cases as a deref of the static does.
+    fn force(&self) -> &'static T;
+
+    /// Like `force`, but returns `Err(InProgress)` instead of waiting if another thread is
+    /// evaluating the initializer.
+    fn try_force(&self) -> Result<&'static T, InProgress>;
+
+    /// Like `force`, but stops waiting for another thread that is evaluating the initializer
+    /// once `budget` is exhausted and returns `Err(InProgress)` then.
+    fn force_within(&self, budget: &mut Budget) -> Result<&'static T, InProgress>;
+
+    /// Returns the value if it has been computed already, without evaluating the
+    /// initializer. Returns `None` while another thread is evaluating the initializer.
+    fn get(&self) -> Option<&'static T>;
+
+    /// Returns `true` if the value has been computed already.
+    fn is_initialized(&self) -> bool {
+        self.get().is_some()
+    }
+}
+
+/// Evaluates the initializer of a lazy static if that has not happened yet.
+///
+/// This is meant for forcing statics at a well defined point, e.g. during boot, such that
+/// latency sensitive code is never the first one to access them.
+pub fn initialize<T, Sized? L: LazyStatic<T>>(lazy: &L) {
+    lazy.force();
+}
+
+/// Returns the value of a lazy static if it has been computed already, without evaluating
+/// the initializer.
+///
+/// This never waits: while another thread is evaluating the initializer, it returns `None`.
+/// That makes it safe to use from contexts such as panic handlers or crash dumps.
+pub fn get<T, Sized? L: LazyStatic<T>>(lazy: &L) -> Option<&'static T> {
+    lazy.get()
+}
+
+/// Error returned by `try_deref` if another thread is evaluating the initializer.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct InProgress;
+
+/// Returns the value of a lazy static like a deref does, but never waits for another thread.
+///
+/// If another thread, or the code interrupted by the current interrupt handler, is evaluating
+/// the initializer, this returns `Err(InProgress)` instead of waiting for a value that might
+/// never be computed.
+pub fn try_deref<T, Sized? L: LazyStatic<T>>(lazy: &L) -> Result<&'static T, InProgress> {
+    lazy.try_force()
+}
+
+/// Returns the value of a lazy static like a deref does, but stops waiting for another
+/// thread's initializer once `budget` is exhausted and returns `Err(InProgress)` then.
+///
+/// The budget is either a number of wait iterations, `timeout::Iterations`, or a deadline of
+/// a user supplied clock, `timeout::Deadline`. Until it is exhausted, this waits like a deref
+/// of the static does, with its wait strategy and the watchdog.
+pub fn deref_timeout<T, Sized? L: LazyStatic<T>, B: Budget>(lazy: &L, mut budget: B)
+                                                            -> Result<&'static T, InProgress> {
+    lazy.force_within(&mut budget)
+}
+
+/// Calls `initialize` for every given lazy static, in order.
+#[macro_export]
+macro_rules! initialize_all {
+    ($($N:expr),*) => {
+        $(::lazy_static_core::initialize(&$N);)*
+    };
+}
+
+/// Creates a `Lazy` that computes its value by calling `$init`, in a static initializer.
+#[macro_export]
+macro_rules! lazy {
+    ($init:expr) => {
+        ::lazy_static_core::Lazy { cell: once_cell!(), init: $init }
+    };
+}
+
+/// Creates an empty `OnceCell`, in a static initializer.
+#[macro_export]
+macro_rules! once_cell {
+    () => {
+        ::lazy_static_core::OnceCell {
+            once: ::lazy_static_core::ONCE_INIT,
+            data: ::core::cell::UnsafeCell { value: ::core::option::Option::None },
+        }
+    };
+}
+
+/// Creates an unlocked `Mutex` that protects `$value`, in a static initializer.
+#[macro_export]
+macro_rules! mutex {
+    ($value:expr) => {
+        ::lazy_static_core::Mutex {
+            locked: ::core::atomic::INIT_ATOMIC_BOOL,
+            data: ::core::cell::UnsafeCell { value: $value },
+        }
+    };
+}
+
+/// Creates an unlocked `RwLock` that protects `$value`, in a static initializer.
+#[macro_export]
+macro_rules! rwlock {
+    ($value:expr) => {
+        ::lazy_static_core::RwLock {
+            state: ::core::atomic::INIT_ATOMIC_UINT,
+            data: ::core::cell::UnsafeCell { value: $value },
+        }
+    };
+}
+
+/// Makes all lazy statics run their initialization inside of the critical section `$cs`, an
+/// expression of a type that implements `critical_section::CriticalSection`.
+///
+/// This requires the cargo feature `critical_section` and has to be used exactly once in the
+/// final binary.
+#[macro_export]
+macro_rules! lazy_static_critical_section {
+    ($cs:expr) => {
+        #[no_mangle]
+        #[doc(hidden)]
+        pub fn lazy_static_core_cs_acquire() -> uint {
+            use lazy_static_core::critical_section::CriticalSection;
+            $cs.acquire()
+        }
+
+        #[no_mangle]
+        #[doc(hidden)]
+        pub fn lazy_static_core_cs_release(token: uint) {
+            use lazy_static_core::critical_section::CriticalSection;
+            $cs.release(token)
+        }
+    };
+}
+
+/// Makes `wait::DefaultStrategy` call `$hook`, a function of type `fn(uint)` that is called
+/// with the number of wait iterations so far, e.g. one that halts the processor until the
+/// next interrupt.
+///
+/// This requires the cargo feature `wait_hook` and has to be used exactly once in the final
+/// binary.
+#[macro_export]
+macro_rules! lazy_static_wait_hook {
+    ($hook:expr) => {
+        #[no_mangle]
+        #[doc(hidden)]
+        pub fn lazy_static_core_wait(iteration: uint) {
+            $hook(iteration)
+        }
+    };
+}
+
+/// Makes recursive initializations of lazy statics panic instead of waiting forever, by
+/// identifying the current thread through `$id`, an expression of type `uint`.
+///
+/// The id must differ between all threads that can be suspended inside of an initializer,
+/// or a thread that finds another one's initializer running on the same id panics instead
+/// of waiting for it. With a preemptive scheduler, that requires a real thread id. The index
+/// of the current core is enough only on systems without preemption besides interrupts.
+///
+/// This requires the cargo feature `thread_id` and has to be used exactly once in the final
+/// binary.
+#[macro_export]
+macro_rules! lazy_static_thread_id {
+    ($id:expr) => {
+        #[no_mangle]
+        #[doc(hidden)]
+        pub fn lazy_static_core_thread_id() -> uint {
+            $id
+        }
+    };
+}
+
+/// Makes all threads that wait for another thread's initializer call `$hook` every
+/// `$iterations` wait iterations, with a description of the lazy static they wait for, e.g.
+/// `lazy static NAME`, and the number of iterations so far. `$hook` is a function of type
+/// `fn(&'static str, uint)`.
+///
+/// This requires the cargo feature `watchdog` and has to be used exactly once in the final
+/// binary.
+#[macro_export]
+macro_rules! lazy_static_watchdog {
+    ($iterations:expr, $hook:expr) => {
+        #[no_mangle]
+        #[doc(hidden)]
+        pub fn lazy_static_core_watchdog_iterations() -> uint {
+            $iterations
+        }
 
-#[cfg(test)] extern crate std;
+        #[no_mangle]
+        #[doc(hidden)]
+        pub fn lazy_static_core_watchdog(what: &'static str, iterations: uint) {
+            $hook(what, iterations)
+        }
+    };
+}
 
 #[macro_export]
 macro_rules! lazy_static_core {
-    (static ref $N:ident : $T:ty = $e:expr; $($t:tt)*) => {
-        lazy_static_core!(PRIV static ref $N : $T = $e; $($t)*);
+    ($(#[$attr:meta])* static ref mut $N:ident : $T:ty
+     = $e:expr $(with $W:expr)*; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PRIV static ref $N : ::lazy_static_core::Mutex<$T>
+                          = ::lazy_static_core::Mutex::new($e)
+                          with lazy_static_core!(MAKE WAIT $($W)*); $($t)*);
     };
-    (pub static ref $N:ident : $T:ty = $e:expr; $($t:tt)*) => {
-        lazy_static_core!(PUB static ref $N : $T = $e; $($t)*);
+    ($(#[$attr:meta])* pub static ref mut $N:ident : $T:ty
+     = $e:expr $(with $W:expr)*; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PUB static ref $N : ::lazy_static_core::Mutex<$T>
+                          = ::lazy_static_core::Mutex::new($e)
+                          with lazy_static_core!(MAKE WAIT $($W)*); $($t)*);
     };
-    ($VIS:ident static ref $N:ident : $T:ty = $e:expr; $($t:tt)*) => {
-        lazy_static_core!(MAKE TY $VIS $N);
-        impl ::core::ops::Deref<$T> for $N {
-            fn deref<'a>(&'a self) -> &'a $T {
-                use core::mem::transmute;
-                use core::atomic::{AtomicBool, INIT_ATOMIC_BOOL, Ordering};
-                use core::kinds::Sync;
+    ($(#[$attr:meta])* static ref $N:ident : $T:ty = $e:expr $(with $W:expr)*; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PRIV static ref $N : $T = $e
+                          with lazy_static_core!(MAKE WAIT $($W)*); $($t)*);
+    };
+    ($(#[$attr:meta])* pub static ref $N:ident : $T:ty = $e:expr $(with $W:expr)*; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PUB static ref $N : $T = $e
+                          with lazy_static_core!(MAKE WAIT $($W)*); $($t)*);
+    };
+    ($(#[$attr:meta])* try static ref $N:ident : Result<$T:ty, $E:ty>
+     = $e:expr $(with $W:expr)*; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PRIV TRY static ref $N : Result<$T, $E> = $e
+                          with lazy_static_core!(MAKE WAIT $($W)*); $($t)*);
+    };
+    ($(#[$attr:meta])* pub try static ref $N:ident : Result<$T:ty, $E:ty>
+     = $e:expr $(with $W:expr)*; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PUB TRY static ref $N : Result<$T, $E> = $e
+                          with lazy_static_core!(MAKE WAIT $($W)*); $($t)*);
+    };
+    ($(#[$attr:meta])* retry static ref $N:ident : $T:ty
+     = $e:expr $(with $W:expr)*; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PRIV RETRY static ref $N : $T = $e
+                          with lazy_static_core!(MAKE WAIT $($W)*); $($t)*);
+    };
+    ($(#[$attr:meta])* pub retry static ref $N:ident : $T:ty
+     = $e:expr $(with $W:expr)*; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PUB RETRY static ref $N : $T = $e
+                          with lazy_static_core!(MAKE WAIT $($W)*); $($t)*);
+    };
+    ($(#[$attr:meta])* static ref $N:ident : $T:ty; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PRIV LATE static ref $N : $T; $($t)*);
+    };
+    ($(#[$attr:meta])* pub static ref $N:ident : $T:ty; $($t:tt)*) => {
+        lazy_static_core!($(#[$attr])* PUB LATE static ref $N : $T; $($t)*);
+    };
+    ($(#[$attr:meta])* $VIS:ident LATE static ref $N:ident : $T:ty; $($t:tt)*) => {
+        lazy_static_core!(MAKE TY $(#[$attr])* $VIS $N);
+        $(#[$attr])*
+        impl $N {
+            #[inline(always)]
+            fn __cell(&self) -> &'static ::lazy_static_core::OnceCell<$T> {
+                static CELL: ::lazy_static_core::OnceCell<$T> = once_cell!();
+                &CELL
+            }
 
-                #[inline(always)]
-                fn require_sync<T: Sync>(_: &T) { }
-                
-                static mut data: *const $T = 0 as *const $T;
-                static INITIALIZED: AtomicBool = INIT_ATOMIC_BOOL;
+            #[inline(always)]
+            fn __force(&self, patience: ::lazy_static_core::timeout::Patience)
+                       -> ::core::result::Result<&'static $T, ::lazy_static_core::InProgress> {
+                let strategy = &::lazy_static_core::wait::DefaultStrategy;
+                let strategy = patience.bound(strategy);
+                let what = concat!("lazy static ", stringify!($N));
+                self.__cell().wait_with(what, strategy.as_ref()).map(|value| match value {
+                    ::core::option::Option::Some(value) => value,
+                    ::core::option::Option::None => {
+                        ::lazy_static_core::once::uninitialized(what)
+                    }
+                })
+            }
 
-                if INITIALIZED.compare_and_swap(false, true, Ordering::SeqCst) == false {
-                    unsafe{data = transmute::<Box<$T>, *const $T>(box() ($e))};
+            #[inline(always)]
+            fn __peek(&self) -> ::core::option::Option<&'static $T> {
+                self.__cell().get()
+            }
+
+            /// Sets the value of this static, or returns `value` in `Err` if it has been set
+            /// already.
+            #[allow(dead_code)]
+            pub fn init(value: $T) -> ::core::result::Result<(), $T> {
+                $N.__cell().set(value)
+            }
+
+            /// Returns the value of this static, or `None` if it has not been set yet.
+            #[allow(dead_code)]
+            pub fn try_get(&self) -> ::core::option::Option<&'static $T> {
+                let what = concat!("lazy static ", stringify!($N));
+                let strategy = ::core::option::Option::Some(
+                    &::lazy_static_core::wait::DefaultStrategy);
+                self.__cell().wait_with(what, strategy).ok().unwrap()
+            }
+        }
+        lazy_static_core!(MAKE IMPLS $(#[$attr])* $N : $T);
+        lazy_static_core!($($t)*);
+    };
+    ($(#[$attr:meta])* $VIS:ident RETRY static ref $N:ident : $T:ty
+     = $e:expr with $W:expr; $($t:tt)*) => {
+        lazy_static_core!(MAKE TY $(#[$attr])* $VIS $N);
+        lazy_static_core!(MAKE CELL $(#[$attr])* $N : $T);
+        $(#[$attr])*
+        impl $N {
+            #[inline(always)]
+            fn __init(&self, patience: ::lazy_static_core::timeout::Patience)
+                      -> ::core::result::Result<::core::option::Option<&'static $T>,
+                                                ::lazy_static_core::InProgress> {
+                let data = self.__data();
+                let init = || match $e {
+                    ::core::option::Option::Some(value) => {
+                        unsafe{*data.get() = ::core::option::Option::Some(value)};
+                        true
+                    }
+                    ::core::option::Option::None => false,
+                };
+                let strategy = &$W;
+                let strategy = patience.bound(strategy);
+                let what = concat!("lazy static ", stringify!($N));
+                self.__once().try_call_once_with(what, strategy.as_ref(), init).map(|completed| {
+                    if completed {
+                        ::core::option::Option::Some(unsafe {self.__get()})
+                    } else {
+                        ::core::option::Option::None
+                    }
+                })
+            }
+
+            #[inline(always)]
+            fn __force(&self, patience: ::lazy_static_core::timeout::Patience)
+                       -> ::core::result::Result<&'static $T, ::lazy_static_core::InProgress> {
+                self.__init(patience).map(|value| match value {
+                    ::core::option::Option::Some(value) => value,
+                    ::core::option::Option::None => {
+                        ::lazy_static_core::once::not_ready(concat!("lazy static ",
+                                                                    stringify!($N)))
+                    }
+                })
+            }
+
+            #[inline(always)]
+            fn __peek(&self) -> ::core::option::Option<&'static $T> {
+                self.__stored()
+            }
+
+            /// Returns the value of this static, evaluating its initializer if no evaluation
+            /// has succeeded yet. Returns `None` if the initializer reported that the value is
+            /// not ready, in which case the next access evaluates it again.
+            #[allow(dead_code)]
+            pub fn try_get(&self) -> ::core::option::Option<&'static $T> {
+                let patience = ::lazy_static_core::timeout::Patience::Forever;
+                self.__init(patience).ok().and_then(|value| value)
+            }
+        }
+        lazy_static_core!(MAKE IMPLS $(#[$attr])* $N : $T);
+        lazy_static_core!($($t)*);
+    };
+    ($(#[$attr:meta])* $VIS:ident TRY static ref $N:ident : Result<$T:ty, $E:ty>
+     = $e:expr with $W:expr; $($t:tt)*) => {
+        lazy_static_core!(MAKE TY $(#[$attr])* $VIS $N);
+        lazy_static_core!(MAKE CELL $(#[$attr])* $N : ::core::result::Result<$T, $E> = $e
+                          with $W);
+        $(#[$attr])*
+        impl $N {
+            #[inline(always)]
+            fn __force(&self, patience: ::lazy_static_core::timeout::Patience)
+                       -> ::core::result::Result<&'static $T, ::lazy_static_core::InProgress> {
+                self.__init(patience).map(|result| match *result {
+                    ::core::result::Result::Ok(ref value) => value,
+                    ::core::result::Result::Err(ref err) => {
+                        let what = concat!("lazy static ", stringify!($N));
+                        ::lazy_static_core::once::failed(what, err)
+                    }
+                })
+            }
+
+            #[inline(always)]
+            fn __peek(&self) -> ::core::option::Option<&'static $T> {
+                match self.__stored() {
+                    ::core::option::Option::Some(&::core::result::Result::Ok(ref value)) => {
+                        ::core::option::Option::Some(value)
+                    }
+                    _ => ::core::option::Option::None,
                 }
+            }
 
-                let static_ref = unsafe {&*data};
-                require_sync(static_ref);
-                static_ref                
+            /// Returns the value of this static, or the error its initializer has returned.
+            #[allow(dead_code)]
+            pub fn try_get(&self) -> ::core::result::Result<&'static $T, &'static $E> {
+                let patience = ::lazy_static_core::timeout::Patience::Forever;
+                match self.__init(patience).ok().unwrap() {
+                    &::core::result::Result::Ok(ref value) => ::core::result::Result::Ok(value),
+                    &::core::result::Result::Err(ref err) => ::core::result::Result::Err(err),
+                }
             }
         }
+        lazy_static_core!(MAKE IMPLS $(#[$attr])* $N : $T);
         lazy_static_core!($($t)*);
     };
-    (MAKE TY PUB $N:ident) => {
+    ($(#[$attr:meta])* $VIS:ident static ref $N:ident : $T:ty
+     = $e:expr with $W:expr; $($t:tt)*) => {
+        lazy_static_core!(MAKE TY $(#[$attr])* $VIS $N);
+        lazy_static_core!(MAKE CELL $(#[$attr])* $N : $T = $e with $W);
+        $(#[$attr])*
+        impl $N {
+            #[inline(always)]
+            fn __force(&self, patience: ::lazy_static_core::timeout::Patience)
+                       -> ::core::result::Result<&'static $T, ::lazy_static_core::InProgress> {
+                self.__init(patience)
+            }
+
+            #[inline(always)]
+            fn __peek(&self) -> ::core::option::Option<&'static $T> {
+                self.__stored()
+            }
+        }
+        lazy_static_core!(MAKE IMPLS $(#[$attr])* $N : $T);
+        lazy_static_core!($($t)*);
+    };
+    (MAKE WAIT) => (::lazy_static_core::wait::DefaultStrategy);
+    (MAKE WAIT $W:expr) => ($W);
+    (MAKE TY $(#[$attr:meta])* PUB $N:ident) => {
+        $(#[$attr])*
         #[allow(non_camel_case_types)]
         #[allow(dead_code)]
         pub struct $N {__private_field: ()}
+        $(#[$attr])*
         #[allow(dead_code)]
         pub static $N: $N = $N {__private_field: ()};
     };
-    (MAKE TY PRIV $N:ident) => {
+    (MAKE TY $(#[$attr:meta])* PRIV $N:ident) => {
+        $(#[$attr])*
         #[allow(non_camel_case_types)]
         #[allow(dead_code)]
         struct $N {__private_field: ()}
+        $(#[$attr])*
         #[allow(dead_code)]
         static $N: $N = $N {__private_field: ()};
     };
+    (MAKE IMPLS $(#[$attr:meta])* $N:ident : $T:ty) => {
+        $(#[$attr])*
+        impl ::lazy_static_core::LazyStatic<$T> for $N {
+            fn force(&self) -> &'static $T {
+                // waiting never fails
+                self.__force(::lazy_static_core::timeout::Patience::Forever).ok().unwrap()
+            }
+
+            fn try_force(&self)
+                         -> ::core::result::Result<&'static $T, ::lazy_static_core::InProgress> {
+                self.__force(::lazy_static_core::timeout::Patience::Never)
+            }
+
+            fn force_within(&self, budget: &mut ::lazy_static_core::timeout::Budget)
+                            -> ::core::result::Result<&'static $T,
+                                                      ::lazy_static_core::InProgress> {
+                self.__force(::lazy_static_core::timeout::Patience::Within(budget))
+            }
+
+            fn get(&self) -> ::core::option::Option<&'static $T> {
+                self.__peek()
+            }
+        }
+        $(#[$attr])*
+        impl ::core::ops::Deref<$T> for $N {
+            fn deref<'a>(&'a self) -> &'a $T {
+                use lazy_static_core::LazyStatic;
+                self.force()
+            }
+        }
+    };
+    (MAKE CELL $(#[$attr:meta])* $N:ident : $T:ty = $e:expr with $W:expr) => {
+        $(#[$attr])*
+        impl $N {
+            #[inline(always)]
+            fn __lazy(&self) -> &'static ::lazy_static_core::Lazy<$T> {
+                fn init() -> $T { $e }
+                static LAZY: ::lazy_static_core::Lazy<$T> = lazy!(init);
+                &LAZY
+            }
+
+            #[inline(always)]
+            fn __init(&self, patience: ::lazy_static_core::timeout::Patience)
+                      -> ::core::result::Result<&'static $T, ::lazy_static_core::InProgress> {
+                #[inline(always)]
+                fn require_sync<T: ::core::kinds::Sync>(_: &T) { }
+
+                let strategy = &$W;
+                let strategy = patience.bound(strategy);
+                let what = concat!("lazy static ", stringify!($N));
+                self.__lazy().force_with(what, strategy.as_ref()).map(|static_ref| {
+                    require_sync(static_ref);
+                    static_ref
+                })
+            }
+
+            #[inline(always)]
+            fn __stored(&self) -> ::core::option::Option<&'static $T> {
+                self.__lazy().get()
+            }
+
+            /// Returns `true` if evaluating the initializer of this static has panicked.
+            #[allow(dead_code)]
+            pub fn is_poisoned(&self) -> bool {
+                self.__lazy().is_poisoned()
+            }
+        }
+    };
+    (MAKE CELL $(#[$attr:meta])* $N:ident : $T:ty) => {
+        $(#[$attr])*
+        impl $N {
+            #[inline(always)]
+            fn __once(&self) -> &'static ::lazy_static_core::Once {
+                static ONCE: ::lazy_static_core::Once = ::lazy_static_core::ONCE_INIT;
+                &ONCE
+            }
+
+            #[inline(always)]
+            fn __data(&self) -> &'static ::core::cell::UnsafeCell<::core::option::Option<$T>> {
+                static DATA: ::core::cell::UnsafeCell<::core::option::Option<$T>> =
+                    ::core::cell::UnsafeCell { value: ::core::option::Option::None };
+                &DATA
+            }
+
+            /// Must only be called after `__once` has completed.
+            #[inline(always)]
+            unsafe fn __get(&self) -> &'static $T {
+                #[inline(always)]
+                fn require_sync<T: ::core::kinds::Sync>(_: &T) { }
+
+                let static_ref = (*self.__data().get()).as_ref().unwrap();
+                require_sync(static_ref);
+                static_ref
+            }
+
+            #[inline(always)]
+            fn __stored(&self) -> ::core::option::Option<&'static $T> {
+                if self.__once().is_completed() {
+                    ::core::option::Option::Some(unsafe {self.__get()})
+                } else {
+                    ::core::option::Option::None
+                }
+            }
+
+            /// Returns `true` if evaluating the initializer of this static has panicked.
+            #[allow(dead_code)]
+            pub fn is_poisoned(&self) -> bool {
+                self.__once().is_poisoned()
+            }
+        }
+    };
     () => ()
 }
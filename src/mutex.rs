@@ -0,0 +1,108 @@
+//! A spin lock that gives exclusive access to the value it protects.
+
+use core::atomic::{AtomicBool, Ordering};
+use core::cell::UnsafeCell;
+use core::kinds::{Send, Sync};
+use core::ops::{Deref, DerefMut, Drop};
+use core::option::{Option, Some, None};
+
+#[cfg(feature = "no_cas")]
+use critical_section::Guard;
+use wait::{WaitStrategy, DefaultStrategy};
+
+/// A lock that gives exclusive access to a value of type `T`, waiting with
+/// `wait::DefaultStrategy` while another thread holds it.
+///
+/// A `Mutex` can be created through `Mutex::new` or, in a static initializer, through the
+/// `mutex!` macro. The `static ref mut` form of `lazy_static_core!` wraps its value in one:
+///
+/// ```ignore
+/// static PORT: Mutex<SerialPort> = mutex!(SerialPort { base: 0x3f8 });
+///
+/// PORT.lock().write_byte(b'!');
+/// ```
+pub struct Mutex<T> {
+    #[doc(hidden)]
+    pub locked: AtomicBool,
+    #[doc(hidden)]
+    pub data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T: Send> Mutex<T> {
+    /// Creates an unlocked `Mutex` that protects `value`.
+    pub fn new(value: T) -> Mutex<T> {
+        Mutex { locked: AtomicBool::new(false), data: UnsafeCell::new(value) }
+    }
+
+    /// Locks the `Mutex`, waiting until no other thread holds it.
+    pub fn lock(&self) -> MutexGuard<T> {
+        let mut iteration = 0;
+        loop {
+            match self.try_lock() {
+                Some(guard) => return guard,
+                None => {
+                    DefaultStrategy.wait(iteration);
+                    iteration += 1;
+                }
+            }
+        }
+    }
+
+    /// Locks the `Mutex` if no other thread holds it, without waiting.
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        if self.acquire() {
+            Some(MutexGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    /// Moves from unlocked to locked and returns `true` if the `Mutex` was unlocked.
+    #[cfg(not(feature = "no_cas"))]
+    #[inline(always)]
+    fn acquire(&self) -> bool {
+        !self.locked.compare_and_swap(false, true, Ordering::SeqCst)
+    }
+
+    /// Moves from unlocked to locked and returns `true` if the `Mutex` was unlocked.
+    ///
+    /// Without compare and swap, the critical section keeps everything else from running
+    /// between the load and the store.
+    #[cfg(feature = "no_cas")]
+    #[inline(always)]
+    fn acquire(&self) -> bool {
+        let _section = Guard::acquire();
+        if self.locked.load(Ordering::SeqCst) {
+            false
+        } else {
+            self.locked.store(true, Ordering::SeqCst);
+            true
+        }
+    }
+}
+
+/// Gives access to the value of a locked `Mutex` and unlocks it when dropped.
+pub struct MutexGuard<'a, T: 'a> {
+    lock: &'a Mutex<T>,
+}
+
+impl<'a, T> Deref<T> for MutexGuard<'a, T> {
+    fn deref<'b>(&'b self) -> &'b T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut<T> for MutexGuard<'a, T> {
+    fn deref_mut<'b>(&'b mut self) -> &'b mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+#[unsafe_destructor]
+impl<'a, T> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,314 @@
+//! A primitive for running a piece of code exactly once.
+//!
+//! `Once` implements the state machine behind all lazily evaluated values of this crate.
+//! Besides `Once` itself, the module contains a few items that have to be public only
+//! because the code generated by `lazy_static_core!` refers to them.
+
+use core::atomic::{AtomicUint, INIT_ATOMIC_UINT, Ordering};
+use core::fmt::Show;
+use core::ops::Drop;
+use core::option::{Option, Some, None};
+use core::result::{Result, Ok, Err};
+
+use InProgress;
+#[cfg(feature = "std")]
+use blocking;
+#[cfg(all(feature = "futex", not(feature = "std")))]
+use futex;
+use critical_section::Guard;
+use timeout::watchdog;
+use thread;
+use trace;
+use wait::{WaitStrategy, DefaultStrategy};
+
+#[doc(hidden)]
+pub const UNINITIALIZED: uint = 0;
+#[doc(hidden)]
+pub const RUNNING: uint = 1;
+#[doc(hidden)]
+pub const COMPLETE: uint = 2;
+#[doc(hidden)]
+pub const POISONED: uint = 3;
+
+/// Guards a piece of code that must run exactly once, even if several threads try to run it
+/// at the same time.
+///
+/// ```ignore
+/// static VECTORS: Once = ONCE_INIT;
+///
+/// VECTORS.call_once(|| register_interrupt_vectors());
+/// ```
+pub struct Once {
+    #[doc(hidden)]
+    pub state: AtomicUint,
+    /// The id of the thread that is running the closure, plus one, or 0 if it is unknown.
+    #[doc(hidden)]
+    pub owner: AtomicUint,
+}
+
+/// Initial value for a `Once`.
+pub const ONCE_INIT: Once = Once { state: INIT_ATOMIC_UINT, owner: INIT_ATOMIC_UINT };
+
+impl Once {
+    /// Runs `f` if no closure was run before and waits until that closure has completed
+    /// otherwise.
+    ///
+    /// If a closure panics, the `Once` is poisoned and this and all further calls panic
+    /// without running their closure.
+    pub fn call_once(&self, f: ||) {
+        // waiting never fails
+        self.call_once_with("Once instance", Some(&DefaultStrategy), f).ok().unwrap()
+    }
+
+    /// Like `call_once`, but describes the guarded value as `what`, e.g. `lazy static NAME`,
+    /// in panic messages. While another thread is running its closure, this waits using
+    /// `strategy` until the strategy gives up, and returns `Err(InProgress)` then or right
+    /// away if `strategy` is `None`.
+    #[doc(hidden)]
+    pub fn call_once_with<W: WaitStrategy>(&self, what: &'static str, strategy: Option<&W>,
+                                           f: ||) -> Result<(), InProgress> {
+        self.try_call_once_with(what, strategy, || { f(); true }).map(|_| ())
+    }
+
+    /// Like `call_once_with`, but `f` may fail by returning `false`. In that case the `Once`
+    /// is reset, such that the next call runs its closure again.
+    ///
+    /// Returns `Ok(true)` once a closure has completed successfully and `Ok(false)` if the
+    /// closure run by this call has failed.
+    #[doc(hidden)]
+    pub fn try_call_once_with<W: WaitStrategy>(&self, what: &'static str, strategy: Option<&W>,
+                                               f: || -> bool) -> Result<bool, InProgress> {
+        trace::touch(what);
+        let mut state = self.state.load(Ordering::SeqCst);
+        let mut iteration = 0;
+        loop {
+            match state {
+                COMPLETE => return Ok(true),
+                POISONED => poisoned(what),
+                UNINITIALIZED => {
+                    // left again after `finish` has published the outcome, or right away if
+                    // another thread has claimed the `Once` first
+                    let _section = Guard::acquire();
+                    state = self.claim();
+                    if state == UNINITIALIZED {
+                        let _running = trace::enter(what);
+                        self.owner.store(owner_id(), Ordering::SeqCst);
+                        let mut finish = Finish { once: self, outcome: POISONED };
+                        let completed = f();
+                        finish.outcome = if completed { COMPLETE } else { UNINITIALIZED };
+                        return Ok(completed);
+                    }
+                }
+                _ => match strategy {
+                    // callers that don't wait never panic, even if they are called from
+                    // within `f`, e.g. by an interrupt handler that has interrupted it
+                    None => return Err(InProgress),
+                    Some(_) if self.is_running_here() => recursive(what),
+                    // without compare and swap, closures run inside of the critical section,
+                    // so nothing but a recursive call from within `f` can observe them
+                    // running while holding it
+                    Some(_) if cfg!(feature = "no_cas") => {
+                        let _section = Guard::acquire();
+                        state = self.state.load(Ordering::SeqCst);
+                        if state == RUNNING {
+                            recursive(what);
+                        }
+                    }
+                    // another thread is running its closure (a recursive call from within
+                    // `f` ends up here, too, and never returns if the current thread can't
+                    // be identified)
+                    Some(strategy) => {
+                        if strategy.give_up(iteration) {
+                            return Err(InProgress);
+                        }
+                        self.wait(strategy, iteration);
+                        iteration += 1;
+                        watchdog(what, iteration);
+                        state = self.state.load(Ordering::SeqCst);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Waits like `call_once_with` while another thread is running its closure, but never
+    /// runs a closure itself.
+    ///
+    /// Returns `Ok(true)` if a closure has completed and `Ok(false)` if none has run yet.
+    #[doc(hidden)]
+    pub fn wait_with<W: WaitStrategy>(&self, what: &'static str, strategy: Option<&W>)
+                                      -> Result<bool, InProgress> {
+        trace::touch(what);
+        let mut state = self.state.load(Ordering::SeqCst);
+        let mut iteration = 0;
+        loop {
+            match state {
+                COMPLETE => return Ok(true),
+                POISONED => poisoned(what),
+                UNINITIALIZED => return Ok(false),
+                // see `try_call_once_with`
+                _ => match strategy {
+                    None => return Err(InProgress),
+                    Some(_) if self.is_running_here() => recursive(what),
+                    Some(_) if cfg!(feature = "no_cas") => {
+                        let _section = Guard::acquire();
+                        state = self.state.load(Ordering::SeqCst);
+                        if state == RUNNING {
+                            recursive(what);
+                        }
+                    }
+                    Some(strategy) => {
+                        if strategy.give_up(iteration) {
+                            return Err(InProgress);
+                        }
+                        self.wait(strategy, iteration);
+                        iteration += 1;
+                        watchdog(what, iteration);
+                        state = self.state.load(Ordering::SeqCst);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Waits for the closure that is running on another thread, according to `strategy`.
+    #[cfg(not(any(feature = "std", feature = "futex")))]
+    #[inline(always)]
+    fn wait<W: WaitStrategy>(&self, strategy: &W, iteration: uint) {
+        strategy.wait(iteration)
+    }
+
+    /// Sleeps until the closure that is running on another thread has finished, or for one
+    /// millisecond at most if the waiting thread has to count its iterations.
+    #[cfg(all(feature = "futex", not(feature = "std")))]
+    #[inline(always)]
+    fn wait<W: WaitStrategy>(&self, strategy: &W, _: uint) {
+        futex::wait(&self.state, counts_iterations(strategy))
+    }
+
+    /// Blocks until the closure that is running on another thread has finished, or for one
+    /// millisecond at most if the waiting thread has to count its iterations.
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    fn wait<W: WaitStrategy>(&self, strategy: &W, _: uint) {
+        blocking::wait(&self.state, counts_iterations(strategy))
+    }
+
+    /// Returns `true` if the closure is running on the current thread, which is only known if
+    /// the current thread can be identified.
+    #[inline(always)]
+    fn is_running_here(&self) -> bool {
+        let id = owner_id();
+        id != 0 && self.owner.load(Ordering::SeqCst) == id
+    }
+
+    /// Moves from `UNINITIALIZED` to `RUNNING` and returns the previous state.
+    #[cfg(not(feature = "no_cas"))]
+    #[inline(always)]
+    fn claim(&self) -> uint {
+        self.state.compare_and_swap(UNINITIALIZED, RUNNING, Ordering::SeqCst)
+    }
+
+    /// Moves from `UNINITIALIZED` to `RUNNING` and returns the previous state.
+    ///
+    /// Must be called inside of the critical section, which keeps everything else from
+    /// running between the load and the store.
+    #[cfg(feature = "no_cas")]
+    #[inline(always)]
+    fn claim(&self) -> uint {
+        let state = self.state.load(Ordering::SeqCst);
+        if state == UNINITIALIZED {
+            self.state.store(RUNNING, Ordering::SeqCst);
+        }
+        state
+    }
+
+    /// Returns `true` if a closure passed to `call_once` has completed.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == COMPLETE
+    }
+
+    /// Returns `true` if a closure passed to `call_once` has panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == POISONED
+    }
+}
+
+/// Publishes the outcome of `f` in `Once::try_call_once_with`, even if `f` unwinds.
+struct Finish<'a> {
+    once: &'a Once,
+    outcome: uint,
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for Finish<'a> {
+    fn drop(&mut self) {
+        self.once.owner.store(0, Ordering::SeqCst);
+        self.once.state.store(self.outcome, Ordering::SeqCst);
+        notify_waiters(&self.once.state);
+    }
+}
+
+/// Returns `true` if a thread that waits with `strategy` has to count its iterations, for a
+/// budget or for the watchdog, and therefore must not sleep for longer than one iteration.
+#[cfg(any(feature = "std", feature = "futex"))]
+#[inline(always)]
+fn counts_iterations<W: WaitStrategy>(strategy: &W) -> bool {
+    strategy.bounded() || cfg!(feature = "watchdog")
+}
+
+/// Returns the id of the current thread plus one, or 0 if it can't be identified.
+#[inline(always)]
+fn owner_id() -> uint {
+    match thread::current() {
+        Some(id) => id + 1,
+        None => 0,
+    }
+}
+
+/// Wakes the threads that are blocked in `Once::wait`.
+#[cfg(feature = "std")]
+#[inline(always)]
+fn notify_waiters(_: &AtomicUint) {
+    blocking::notify_all()
+}
+
+/// Wakes the threads that sleep in `Once::wait` on `state`.
+#[cfg(all(feature = "futex", not(feature = "std")))]
+#[inline(always)]
+fn notify_waiters(state: &AtomicUint) {
+    futex::wake_all(state)
+}
+
+/// Does nothing, as waiting threads check the state on their own.
+#[cfg(not(any(feature = "std", feature = "futex")))]
+#[inline(always)]
+fn notify_waiters(_: &AtomicUint) {}
+
+/// Panics with the message used for all accesses to a poisoned value.
+fn poisoned(what: &'static str) -> ! {
+    panic!("{} was poisoned", what)
+}
+
+/// Panics with the message used if a value is accessed by its own initializer.
+fn recursive(what: &'static str) -> ! {
+    panic!("{} was initialized recursively", what)
+}
+
+/// Panics with the message used for all derefs of a late initialized static before `init`.
+#[doc(hidden)]
+pub fn uninitialized(what: &'static str) -> ! {
+    panic!("{} was dereferenced before it was initialized", what)
+}
+
+/// Panics with the message used for all derefs of a `try static ref` whose initializer failed.
+#[doc(hidden)]
+pub fn failed<E: Show>(what: &'static str, err: &E) -> ! {
+    panic!("{} failed to initialize: {}", what, err)
+}
+
+/// Panics with the message used for all derefs of a `retry static ref` that is not ready yet.
+#[doc(hidden)]
+pub fn not_ready(what: &'static str) -> ! {
+    panic!("{} is not ready yet", what)
+}
@@ -0,0 +1,95 @@
+//! A cell that is written at most once.
+
+use core::cell::UnsafeCell;
+use core::option::{Option, Some, None};
+use core::result::{Result, Ok, Err};
+
+use InProgress;
+use once::{Once, ONCE_INIT};
+use wait::{WaitStrategy, DefaultStrategy};
+
+/// Describes a `OnceCell` in panic messages.
+const WHAT: &'static str = "OnceCell instance";
+
+/// A cell that is written at most once, either explicitly through `set` or by the first call
+/// to `get_or_init`, and that can be read through shared references afterwards.
+///
+/// A `OnceCell` can be a struct field, created through `OnceCell::new`, or a static, created
+/// through the `once_cell!` macro:
+///
+/// ```ignore
+/// static BOOT_INFO: OnceCell<BootInfo> = once_cell!();
+/// ```
+pub struct OnceCell<T> {
+    #[doc(hidden)]
+    pub once: Once,
+    #[doc(hidden)]
+    pub data: UnsafeCell<Option<T>>,
+}
+
+impl<T> OnceCell<T> {
+    /// Creates an empty `OnceCell`.
+    pub fn new() -> OnceCell<T> {
+        OnceCell { once: ONCE_INIT, data: UnsafeCell::new(None) }
+    }
+
+    /// Returns the value if the cell has been written already.
+    ///
+    /// This never waits: while another thread is writing the cell, it returns `None`.
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            unsafe { (*self.data.get()).as_ref() }
+        } else {
+            None
+        }
+    }
+
+    /// Writes `value` to the cell, or returns it in `Err` if the cell has been written
+    /// already or another thread is writing it. Panics if the cell is poisoned.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        let _ = self.once.call_once_with(WHAT, None::<&DefaultStrategy>, || unsafe {
+            *self.data.get() = value.take()
+        });
+        match value {
+            Some(value) => Err(value),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the value, writing the result of `f` to the cell first if it is empty.
+    ///
+    /// `f` is called at most once, even if several threads call this at the same time. If it
+    /// panics, the cell is poisoned and this and all further calls panic.
+    pub fn get_or_init(&self, f: || -> T) -> &T {
+        // waiting never fails
+        self.get_or_init_with(WHAT, Some(&DefaultStrategy), f).ok().unwrap()
+    }
+
+    /// Returns `true` if a closure passed to `get_or_init` has panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.once.is_poisoned()
+    }
+
+    /// Like `get_or_init`, but describes the cell as `what` in panic messages and waits
+    /// according to `strategy`, or not at all if that is `None`.
+    #[doc(hidden)]
+    pub fn get_or_init_with<W: WaitStrategy>(&self, what: &'static str, strategy: Option<&W>,
+                                             f: || -> T) -> Result<&T, InProgress> {
+        self.once.call_once_with(what, strategy, || unsafe {
+            *self.data.get() = Some(f())
+        }).map(|()| unsafe {
+            (*self.data.get()).as_ref().unwrap()
+        })
+    }
+
+    /// Like `get`, but waits according to `strategy`, or not at all if that is `None`, while
+    /// another thread is writing the cell.
+    #[doc(hidden)]
+    pub fn wait_with<W: WaitStrategy>(&self, what: &'static str, strategy: Option<&W>)
+                                      -> Result<Option<&T>, InProgress> {
+        self.once.wait_with(what, strategy).map(|completed| {
+            if completed { unsafe { (*self.data.get()).as_ref() } } else { None }
+        })
+    }
+}
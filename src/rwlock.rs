@@ -0,0 +1,164 @@
+//! A spin lock that gives either shared or exclusive access to the value it protects.
+
+use core::atomic::{AtomicUint, Ordering};
+use core::cell::UnsafeCell;
+use core::kinds::{Send, Sync};
+use core::ops::{Deref, DerefMut, Drop};
+use core::option::{Option, Some, None};
+use core::uint;
+
+#[cfg(feature = "no_cas")]
+use critical_section::Guard;
+use wait::{WaitStrategy, DefaultStrategy};
+
+/// The state of an `RwLock` that is locked for writing. All smaller states count readers.
+const WRITER: uint = uint::MAX;
+
+/// A lock that gives either shared access to any number of readers or exclusive access to a
+/// single writer of a value of type `T`, waiting with `wait::DefaultStrategy` while it is
+/// locked incompatibly.
+///
+/// Readers are not kept from locking while a writer waits, so constant reading can starve
+/// writers. An `RwLock` can be created through `RwLock::new` or, in a static initializer,
+/// through the `rwlock!` macro.
+pub struct RwLock<T> {
+    #[doc(hidden)]
+    pub state: AtomicUint,
+    #[doc(hidden)]
+    pub data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+impl<T: Send + Sync> RwLock<T> {
+    /// Creates an unlocked `RwLock` that protects `value`.
+    pub fn new(value: T) -> RwLock<T> {
+        RwLock { state: AtomicUint::new(0), data: UnsafeCell::new(value) }
+    }
+
+    /// Locks the `RwLock` for reading, waiting until no writer holds it.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        let mut iteration = 0;
+        loop {
+            match self.try_read() {
+                Some(guard) => return guard,
+                None => {
+                    DefaultStrategy.wait(iteration);
+                    iteration += 1;
+                }
+            }
+        }
+    }
+
+    /// Locks the `RwLock` for reading if no writer holds it, without waiting.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        let mut state = self.state.load(Ordering::SeqCst);
+        while state != WRITER {
+            let previous = self.transition(state, state + 1);
+            if previous == state {
+                return Some(RwLockReadGuard { lock: self });
+            }
+            state = previous;
+        }
+        None
+    }
+
+    /// Locks the `RwLock` for writing, waiting until neither readers nor a writer hold it.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        let mut iteration = 0;
+        loop {
+            match self.try_write() {
+                Some(guard) => return guard,
+                None => {
+                    DefaultStrategy.wait(iteration);
+                    iteration += 1;
+                }
+            }
+        }
+    }
+
+    /// Locks the `RwLock` for writing if neither readers nor a writer hold it, without
+    /// waiting.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        if self.transition(0, WRITER) == 0 {
+            Some(RwLockWriteGuard { lock: self })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> RwLock<T> {
+    /// Moves from state `old` to state `new` and returns the previous state.
+    #[cfg(not(feature = "no_cas"))]
+    #[inline(always)]
+    fn transition(&self, old: uint, new: uint) -> uint {
+        self.state.compare_and_swap(old, new, Ordering::SeqCst)
+    }
+
+    /// Moves from state `old` to state `new` and returns the previous state.
+    ///
+    /// Without compare and swap, the critical section keeps everything else from running
+    /// between the load and the store.
+    #[cfg(feature = "no_cas")]
+    #[inline(always)]
+    fn transition(&self, old: uint, new: uint) -> uint {
+        let _section = Guard::acquire();
+        let state = self.state.load(Ordering::SeqCst);
+        if state == old {
+            self.state.store(new, Ordering::SeqCst);
+        }
+        state
+    }
+}
+
+/// Gives shared access to the value of an `RwLock` locked for reading and unlocks it when
+/// dropped.
+pub struct RwLockReadGuard<'a, T: 'a> {
+    lock: &'a RwLock<T>,
+}
+
+impl<'a, T> Deref<T> for RwLockReadGuard<'a, T> {
+    fn deref<'b>(&'b self) -> &'b T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+#[unsafe_destructor]
+impl<'a, T> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        let mut state = self.lock.state.load(Ordering::SeqCst);
+        loop {
+            let previous = self.lock.transition(state, state - 1);
+            if previous == state {
+                return;
+            }
+            state = previous;
+        }
+    }
+}
+
+/// Gives exclusive access to the value of an `RwLock` locked for writing and unlocks it when
+/// dropped.
+pub struct RwLockWriteGuard<'a, T: 'a> {
+    lock: &'a RwLock<T>,
+}
+
+impl<'a, T> Deref<T> for RwLockWriteGuard<'a, T> {
+    fn deref<'b>(&'b self) -> &'b T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut<T> for RwLockWriteGuard<'a, T> {
+    fn deref_mut<'b>(&'b mut self) -> &'b mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+#[unsafe_destructor]
+impl<'a, T> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,43 @@
+//! Identification of the current thread, for detecting recursive initializations.
+//!
+//! With the cargo feature `thread_id`, the final binary provides the id through
+//! `lazy_static_thread_id!`, e.g. the index of the current core on systems without
+//! preemption besides interrupts. With the cargo feature `std`, the id is the address of a
+//! thread local. Otherwise, the current thread can't be identified.
+
+use core::option::Option;
+
+/// Returns an id of the current thread that differs from the ids of all other running
+/// threads, or `None` if the current thread can't be identified.
+#[cfg(feature = "thread_id")]
+#[doc(hidden)]
+#[inline(always)]
+pub fn current() -> Option<uint> {
+    Option::Some(unsafe { lazy_static_core_thread_id() })
+}
+
+/// Returns an id of the current thread that differs from the ids of all other running
+/// threads, or `None` if the current thread can't be identified.
+#[cfg(all(feature = "std", not(feature = "thread_id")))]
+#[doc(hidden)]
+#[inline(always)]
+pub fn current() -> Option<uint> {
+    #[thread_local]
+    static MARKER: u8 = 0;
+
+    Option::Some(&MARKER as *const u8 as uint)
+}
+
+/// Returns an id of the current thread that differs from the ids of all other running
+/// threads, or `None` if the current thread can't be identified.
+#[cfg(not(any(feature = "std", feature = "thread_id")))]
+#[doc(hidden)]
+#[inline(always)]
+pub fn current() -> Option<uint> {
+    Option::None
+}
+
+#[cfg(feature = "thread_id")]
+extern "Rust" {
+    fn lazy_static_core_thread_id() -> uint;
+}
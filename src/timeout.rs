@@ -0,0 +1,131 @@
+//! Bounded waits for initializers that may hang.
+//!
+//! `deref_timeout` gives up waiting for another thread's initializer once a `Budget` is
+//! exhausted, either after a number of wait iterations or at a deadline of a user supplied
+//! `Clock`. Until then it waits like a deref of the same static, i.e. with the static's wait
+//! strategy or the sleeping waits of the `std` and `futex` features. With the cargo feature
+//! `watchdog`, all waits additionally report the lazy static they are stuck on to a hook
+//! configured through `lazy_static_watchdog!`.
+
+use core::cell::RefCell;
+use core::option::{Option, Some, None};
+
+use wait::WaitStrategy;
+
+/// A source of time, e.g. a timer register or the tick count of an operating system.
+pub trait Clock {
+    /// Returns the current time, in units of the implementation's choice.
+    fn now(&self) -> u64;
+}
+
+/// Decides when `deref_timeout` stops waiting.
+pub trait Budget {
+    /// Called before every wait iteration; `iteration` counts the iterations before this
+    /// one. Returns `true` if no more waiting is allowed.
+    fn exhausted(&mut self, iteration: uint) -> bool;
+}
+
+/// A budget of a fixed number of wait iterations.
+pub struct Iterations(pub uint);
+
+impl Budget for Iterations {
+    fn exhausted(&mut self, iteration: uint) -> bool {
+        let Iterations(limit) = *self;
+        iteration >= limit
+    }
+}
+
+/// A budget that lasts until a `Clock` reaches a deadline.
+pub struct Deadline<'a, C: 'a> {
+    clock: &'a C,
+    deadline: u64,
+}
+
+impl<'a, C: Clock> Deadline<'a, C> {
+    /// Creates a budget that lasts for `duration` units of `clock` from now on.
+    pub fn after(clock: &'a C, duration: u64) -> Deadline<'a, C> {
+        Deadline { clock: clock, deadline: clock.now() + duration }
+    }
+}
+
+impl<'a, C: Clock> Budget for Deadline<'a, C> {
+    fn exhausted(&mut self, _: uint) -> bool {
+        self.clock.now() >= self.deadline
+    }
+}
+
+/// How long an access to a lazy static waits while another thread evaluates its initializer.
+#[doc(hidden)]
+pub enum Patience<'a> {
+    /// Returns `Err(InProgress)` right away.
+    Never,
+    /// Waits until the initializer has finished.
+    Forever,
+    /// Waits until the initializer has finished or the budget is exhausted.
+    Within(&'a mut (Budget + 'a)),
+}
+
+impl<'a> Patience<'a> {
+    /// Returns the strategy to wait with, which is `strategy` limited by the budget, or
+    /// `None` if the access must not wait at all.
+    pub fn bound<'b, W: WaitStrategy>(self, strategy: &'b W) -> Option<Bounded<'a, 'b, W>> {
+        match self {
+            Patience::Never => None,
+            Patience::Forever => Some(Bounded { strategy: strategy, budget: None }),
+            Patience::Within(budget) => {
+                Some(Bounded { strategy: strategy, budget: Some(RefCell::new(budget)) })
+            }
+        }
+    }
+}
+
+/// Waits with another strategy until a budget, if any, is exhausted.
+#[doc(hidden)]
+pub struct Bounded<'a, 'b, W: 'b> {
+    strategy: &'b W,
+    budget: Option<RefCell<&'a mut (Budget + 'a)>>,
+}
+
+impl<'a, 'b, W: WaitStrategy> WaitStrategy for Bounded<'a, 'b, W> {
+    #[inline(always)]
+    fn wait(&self, iteration: uint) {
+        self.strategy.wait(iteration)
+    }
+
+    #[inline(always)]
+    fn give_up(&self, iteration: uint) -> bool {
+        match self.budget {
+            Some(ref budget) => budget.borrow_mut().exhausted(iteration),
+            None => false,
+        }
+    }
+
+    #[inline(always)]
+    fn bounded(&self) -> bool {
+        self.budget.is_some()
+    }
+}
+
+/// Reports `what` to the configured watchdog hook if `iteration` is a multiple of the
+/// configured iteration count.
+#[cfg(feature = "watchdog")]
+#[doc(hidden)]
+#[inline(always)]
+pub fn watchdog(what: &'static str, iteration: uint) {
+    let limit = unsafe { lazy_static_core_watchdog_iterations() };
+    if limit != 0 && iteration % limit == 0 {
+        unsafe { lazy_static_core_watchdog(what, iteration) }
+    }
+}
+
+/// Does nothing, as no watchdog hook is configured without the `watchdog` feature.
+#[cfg(not(feature = "watchdog"))]
+#[doc(hidden)]
+#[inline(always)]
+pub fn watchdog(_: &'static str, _: uint) {}
+
+#[cfg(feature = "watchdog")]
+extern "Rust" {
+    fn lazy_static_core_watchdog_iterations() -> uint;
+    fn lazy_static_core_watchdog(what: &'static str, iterations: uint);
+}
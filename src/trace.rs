@@ -0,0 +1,309 @@
+//! Recording of the dependencies between lazy statics, for the `trace` feature.
+//!
+//! With the cargo feature `trace`, every access to a lazy static while the initializer of
+//! another one is running records an edge from the initializing static to the accessed one,
+//! and every initialization records its position in the overall initialization order.
+//! `write_dot` writes the resulting graph in the Graphviz DOT format.
+//!
+//! Statics are identified by their description in panic messages, e.g. `lazy static NAME`,
+//! so all `Lazy`, `OnceCell` and `Once` instances used without the macro share one node per
+//! type. The initializers that are currently running are tracked per thread if the current
+//! thread can be identified, i.e. with the cargo feature `std` or `thread_id`. Otherwise
+//! they are tracked for the whole program, so initializations that run on several threads
+//! at the same time may be recorded with wrong edges.
+//!
+//! Recording an access never waits for another thread: while the graph is locked by another
+//! one for too long, e.g. by code that an interrupt handler has interrupted, the access is
+//! not recorded. Only the end of a recorded initialization waits for the lock, which can
+//! then be held only by another core that is about to release it. Accesses outside of any initializer take a single atomic load. The graph is
+//! stored in fixed size tables, and statics and edges that don't fit anymore are not
+//! recorded either. The DOT output notes both cases in a comment.
+
+#[cfg(feature = "trace")]
+use core::atomic::{AtomicBool, INIT_ATOMIC_BOOL, AtomicUint, INIT_ATOMIC_UINT, Ordering};
+#[cfg(feature = "trace")]
+use core::cell::UnsafeCell;
+#[cfg(feature = "trace")]
+use core::fmt::{mod, FormatWriter};
+#[cfg(feature = "trace")]
+use core::iter::range;
+#[cfg(feature = "trace")]
+use core::ops::Drop;
+#[cfg(feature = "trace")]
+use core::option::{Option, Some, None};
+#[cfg(feature = "trace")]
+use core::result::Ok;
+
+#[cfg(feature = "trace")]
+use mutex::{Mutex, MutexGuard};
+#[cfg(feature = "trace")]
+use thread;
+#[cfg(feature = "trace")]
+use wait::spin_loop_hint;
+
+/// The maximal number of recorded statics.
+pub const MAX_NODES: uint = 64;
+/// The maximal number of recorded edges.
+pub const MAX_EDGES: uint = 256;
+/// The maximal number of initializations that are tracked as running at the same time, on
+/// all threads together.
+pub const MAX_DEPTH: uint = 16;
+/// The number of attempts to lock the graph before an access is not recorded.
+pub const LOCK_ATTEMPTS: uint = 64;
+
+#[cfg(feature = "trace")]
+struct Graph {
+    nodes: [Option<&'static str>, ..MAX_NODES],
+    /// The position of each node in the initialization order, starting at 1, or 0 if it has
+    /// not been initialized.
+    order: [uint, ..MAX_NODES],
+    initialized: uint,
+    edges: [Option<(uint, uint)>, ..MAX_EDGES],
+    /// The threads, see `thread_key`, and nodes whose initializers are currently running,
+    /// innermost last.
+    running: [(uint, uint), ..MAX_DEPTH],
+    depth: uint,
+    overflowed: bool,
+}
+
+#[cfg(feature = "trace")]
+static GRAPH: Mutex<Graph> = Mutex {
+    locked: INIT_ATOMIC_BOOL,
+    data: UnsafeCell {
+        value: Graph {
+            nodes: [None, ..MAX_NODES],
+            order: [0, ..MAX_NODES],
+            initialized: 0,
+            edges: [None, ..MAX_EDGES],
+            running: [(0, 0), ..MAX_DEPTH],
+            depth: 0,
+            overflowed: false,
+        },
+    },
+};
+
+/// A copy of `Graph::depth` that can be read without locking the graph. It is only written
+/// with the graph locked, so it needs no read-modify-write atomics, which `no_cas` targets
+/// lack.
+#[cfg(feature = "trace")]
+static RUNNING: AtomicUint = INIT_ATOMIC_UINT;
+
+/// Set if an access was not recorded because the graph was locked.
+#[cfg(feature = "trace")]
+static DROPPED: AtomicBool = INIT_ATOMIC_BOOL;
+
+/// Locks the graph, or returns `None` and sets `DROPPED` if that fails `LOCK_ATTEMPTS` times.
+#[cfg(feature = "trace")]
+fn try_lock() -> Option<MutexGuard<'static, Graph>> {
+    for _ in range(0, LOCK_ATTEMPTS) {
+        match GRAPH.try_lock() {
+            Some(guard) => return Some(guard),
+            None => spin_loop_hint(),
+        }
+    }
+    DROPPED.store(true, Ordering::SeqCst);
+    None
+}
+
+/// Returns the id of the current thread plus one, or 0 for all threads if the current thread
+/// can't be identified.
+#[cfg(feature = "trace")]
+fn thread_key() -> uint {
+    match thread::current() {
+        Some(id) => id + 1,
+        None => 0,
+    }
+}
+
+#[cfg(feature = "trace")]
+impl Graph {
+    /// Returns the index of the node for `what`, adding it if necessary.
+    fn node(&mut self, what: &'static str) -> Option<uint> {
+        for i in range(0, MAX_NODES) {
+            match self.nodes[i] {
+                Some(node) if node == what => return Some(i),
+                Some(_) => {}
+                None => {
+                    self.nodes[i] = Some(what);
+                    return Some(i);
+                }
+            }
+        }
+        self.overflowed = true;
+        None
+    }
+
+    /// Adds the edge from `from` to `to` unless it has been recorded already.
+    fn edge(&mut self, from: uint, to: uint) {
+        for i in range(0, MAX_EDGES) {
+            match self.edges[i] {
+                Some(edge) if edge == (from, to) => return,
+                Some(_) => {}
+                None => {
+                    self.edges[i] = Some((from, to));
+                    return;
+                }
+            }
+        }
+        self.overflowed = true;
+    }
+
+    /// Returns the node of the innermost initializer that is running on `thread`.
+    fn innermost(&self, thread: uint) -> Option<uint> {
+        for i in range(0, self.depth).rev() {
+            let (owner, node) = self.running[i];
+            if owner == thread {
+                return Some(node);
+            }
+        }
+        None
+    }
+}
+
+/// Records an access to the static described by `what`.
+#[cfg(feature = "trace")]
+#[doc(hidden)]
+pub fn touch(what: &'static str) {
+    if RUNNING.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+    let mut guard = match try_lock() {
+        Some(guard) => guard,
+        None => return,
+    };
+    let graph = &mut *guard;
+    let from = match graph.innermost(thread_key()) {
+        Some(from) => from,
+        None => return,
+    };
+    let to = match graph.node(what) {
+        Some(to) => to,
+        None => return,
+    };
+    if from != to {
+        graph.edge(from, to);
+    }
+}
+
+/// Does nothing, as nothing is recorded without the `trace` feature.
+#[cfg(not(feature = "trace"))]
+#[doc(hidden)]
+#[inline(always)]
+pub fn touch(_: &'static str) {}
+
+/// Records that the initializer of the static described by `what` starts running, until the
+/// returned guard is dropped.
+#[cfg(feature = "trace")]
+#[doc(hidden)]
+pub fn enter(what: &'static str) -> Running {
+    let mut guard = match try_lock() {
+        Some(guard) => guard,
+        None => return Running { entry: None },
+    };
+    let graph = &mut *guard;
+    let node = match graph.node(what) {
+        Some(node) => node,
+        None => return Running { entry: None },
+    };
+    if graph.order[node] == 0 {
+        graph.initialized += 1;
+        graph.order[node] = graph.initialized;
+    }
+    if graph.depth == MAX_DEPTH {
+        graph.overflowed = true;
+        return Running { entry: None };
+    }
+    let entry = (thread_key(), node);
+    let depth = graph.depth;
+    graph.running[depth] = entry;
+    graph.depth += 1;
+    RUNNING.store(graph.depth, Ordering::SeqCst);
+    Running { entry: Some(entry) }
+}
+
+/// Does nothing, as nothing is recorded without the `trace` feature.
+#[cfg(not(feature = "trace"))]
+#[doc(hidden)]
+#[inline(always)]
+pub fn enter(_: &'static str) -> Running {
+    Running
+}
+
+/// Keeps an initializer recorded as running for as long as it lives.
+#[cfg(feature = "trace")]
+#[doc(hidden)]
+pub struct Running {
+    entry: Option<(uint, uint)>,
+}
+
+/// Keeps an initializer recorded as running for as long as it lives.
+#[cfg(not(feature = "trace"))]
+#[doc(hidden)]
+pub struct Running;
+
+#[cfg(feature = "trace")]
+impl Drop for Running {
+    fn drop(&mut self) {
+        let entry = match self.entry {
+            Some(entry) => entry,
+            None => return,
+        };
+        // the entry must be removed, so this waits for the lock; `enter` has found it
+        // unlocked on this thread, so the thread holding it now isn't one that this thread
+        // has interrupted and releases it soon
+        let mut guard = GRAPH.lock();
+        let graph = &mut *guard;
+        // remove the innermost entry, which is the last one unless initializers run on
+        // several threads at the same time
+        for i in range(0, graph.depth).rev() {
+            if graph.running[i] == entry {
+                for j in range(i, graph.depth - 1) {
+                    graph.running[j] = graph.running[j + 1];
+                }
+                graph.depth -= 1;
+                RUNNING.store(graph.depth, Ordering::SeqCst);
+                return;
+            }
+        }
+    }
+}
+
+/// Writes the recorded graph to `out`, in the Graphviz DOT format.
+///
+/// Every static is a node labeled with its description and its position in the
+/// initialization order, and an edge from `A` to `B` means that `B` was accessed while the
+/// initializer of `A` was running.
+///
+/// Unlike recording, this waits while another thread records an access, so it must not be
+/// called from an interrupt handler that may interrupt an access to a lazy static.
+#[cfg(feature = "trace")]
+pub fn write_dot<W: FormatWriter>(out: &mut W) -> fmt::Result {
+    let guard = GRAPH.lock();
+    let graph = &*guard;
+    try!(writeln!(out, "digraph lazy_statics {{"));
+    if graph.overflowed {
+        try!(writeln!(out, "    // some statics or edges did not fit and are missing"));
+    }
+    if DROPPED.load(Ordering::SeqCst) {
+        try!(writeln!(out, "    // some accesses found the graph locked and are missing"));
+    }
+    for i in range(0, MAX_NODES) {
+        match graph.nodes[i] {
+            Some(what) if graph.order[i] == 0 => {
+                try!(writeln!(out, "    n{} [label=\"{}\"];", i, what))
+            }
+            Some(what) => {
+                try!(writeln!(out, "    n{} [label=\"{} (#{})\"];", i, what, graph.order[i]))
+            }
+            None => break,
+        }
+    }
+    for i in range(0, MAX_EDGES) {
+        match graph.edges[i] {
+            Some((from, to)) => try!(writeln!(out, "    n{} -> n{};", from, to)),
+            None => break,
+        }
+    }
+    try!(writeln!(out, "}}"));
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Single threaded versions of `Lazy` and `OnceCell`.
+//!
+//! The types of this module carry no atomics and don't wait for other threads, so they work
+//! for values that are not `Sync`, such as `Cell`s and `RefCell`s. They are not `Sync`
+//! themselves, so they can be used in locals and struct fields, but not in statics.
+//!
+//! Their API mirrors the thread safe types of the crate root, except that there is nothing
+//! to wait for and nothing to poison: if `init` or the closure passed to `get_or_init`
+//! panics, the cell simply stays empty. `try_force` and `is_poisoned` exist anyway, such that
+//! code can move between the two versions, but never fail and never return `true`.
+
+use core::cell::UnsafeCell;
+use core::kinds::marker::NoSync;
+use core::ops::{Deref, Fn};
+use core::option::{Option, Some, None};
+use core::result::{Result, Ok, Err};
+
+use InProgress;
+
+/// A cell that is written at most once, either explicitly through `set` or by the first call
+/// to `get_or_init`, and that can be read through shared references afterwards.
+pub struct OnceCell<T> {
+    data: UnsafeCell<Option<T>>,
+    nosync: NoSync,
+}
+
+impl<T> OnceCell<T> {
+    /// Creates an empty `OnceCell`.
+    pub fn new() -> OnceCell<T> {
+        OnceCell { data: UnsafeCell::new(None), nosync: NoSync }
+    }
+
+    /// Returns the value if the cell has been written already.
+    pub fn get(&self) -> Option<&T> {
+        unsafe { (*self.data.get()).as_ref() }
+    }
+
+    /// Writes `value` to the cell, or returns it in `Err` if the cell has been written
+    /// already.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        match self.get() {
+            Some(_) => Err(value),
+            None => {
+                unsafe { *self.data.get() = Some(value) };
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns `false`, as a panicking closure leaves the cell empty instead of poisoning it.
+    pub fn is_poisoned(&self) -> bool {
+        false
+    }
+
+    /// Returns the value, writing the result of `f` to the cell first if it is empty.
+    ///
+    /// Panics if `f` writes the cell itself, as that would invalidate the value.
+    pub fn get_or_init(&self, f: || -> T) -> &T {
+        match self.get() {
+            Some(value) => return value,
+            None => {}
+        }
+        let value = f();
+        if self.set(value).is_err() {
+            panic!("OnceCell instance was initialized recursively")
+        }
+        self.get().unwrap()
+    }
+}
+
+/// A value that is computed by calling `init` on first access.
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    init: F,
+}
+
+impl<T, F: Fn() -> T> Lazy<T, F> {
+    /// Creates a `Lazy` that computes its value through `init`.
+    pub fn new(init: F) -> Lazy<T, F> {
+        Lazy { cell: OnceCell::new(), init: init }
+    }
+
+    /// Returns the value, calling `init` first if that has not happened yet.
+    pub fn force(&self) -> &T {
+        self.cell.get_or_init(|| (self.init)())
+    }
+
+    /// Like `force`, but never returns `Err(InProgress)`, as no other thread can be calling
+    /// `init`.
+    pub fn try_force(&self) -> Result<&T, InProgress> {
+        Ok(self.force())
+    }
+
+    /// Returns the value if it has been computed already, without calling `init`.
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+
+    /// Returns `false`, as a panicking `init` leaves the value uncomputed instead of
+    /// poisoning it.
+    pub fn is_poisoned(&self) -> bool {
+        self.cell.is_poisoned()
+    }
+}
+
+impl<T, F: Fn() -> T> Deref<T> for Lazy<T, F> {
+    fn deref<'a>(&'a self) -> &'a T {
+        self.force()
+    }
+}
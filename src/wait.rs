@@ -0,0 +1,114 @@
+//! Strategies for waiting until another thread has evaluated the initializer of a lazy static.
+//!
+//! A strategy is selected per static by appending `with STRATEGY` to its declaration, e.g.
+//! `static ref NAME: TYPE = EXPR with ::lazy_static_core::wait::Backoff;`. All other statics
+//! use `DefaultStrategy`, which can be configured for the whole crate graph through cargo
+//! features.
+
+use core::cmp::min;
+use core::iter::range;
+
+/// Decides what a thread does while it waits for another thread to evaluate an initializer.
+pub trait WaitStrategy {
+    /// Called in a loop for as long as the initializer is running on another thread.
+    /// `iteration` counts the calls before this one during the current wait.
+    fn wait(&self, iteration: uint);
+
+    /// Called before every wait iteration; returns `true` to stop waiting, such that the
+    /// access returns `Err(InProgress)`. Only `deref_timeout` ever gives up, so all other
+    /// strategies keep the default, which never does.
+    #[doc(hidden)]
+    #[inline(always)]
+    fn give_up(&self, _: uint) -> bool {
+        false
+    }
+
+    /// Returns `true` if `give_up` may ever return `true`, such that threads that sleep
+    /// instead of running the strategy have to wake up regularly to call it.
+    #[doc(hidden)]
+    #[inline(always)]
+    fn bounded(&self) -> bool {
+        false
+    }
+}
+
+/// Busy waits, telling the processor that it is in a spin loop.
+pub struct Spin;
+
+impl WaitStrategy for Spin {
+    #[inline(always)]
+    fn wait(&self, _: uint) {
+        spin_loop_hint();
+    }
+}
+
+/// Busy waits like `Spin`, but doubles the time spent per iteration, up to
+/// `2^MAX_BACKOFF_SHIFT` spin loop hints.
+pub struct Backoff;
+
+/// The maximal exponent used by `Backoff`.
+pub const MAX_BACKOFF_SHIFT: uint = 10;
+
+impl WaitStrategy for Backoff {
+    fn wait(&self, iteration: uint) {
+        for _ in range(0, 1u << min(iteration, MAX_BACKOFF_SHIFT)) {
+            spin_loop_hint();
+        }
+    }
+}
+
+/// Calls a user supplied function, e.g. one that halts the processor until the next
+/// interrupt or one that yields to a scheduler.
+pub struct Hook(pub fn(uint));
+
+impl WaitStrategy for Hook {
+    #[inline(always)]
+    fn wait(&self, iteration: uint) {
+        let Hook(f) = *self;
+        f(iteration)
+    }
+}
+
+/// The strategy of all lazy statics that don't select one.
+///
+/// By default, this is `Spin`. With the cargo feature `wait_backoff` it is `Backoff`, and
+/// with the cargo feature `wait_hook` it calls the function configured exactly once in the
+/// final binary through `lazy_static_wait_hook!`.
+pub struct DefaultStrategy;
+
+impl WaitStrategy for DefaultStrategy {
+    #[cfg(not(any(feature = "wait_backoff", feature = "wait_hook")))]
+    #[inline(always)]
+    fn wait(&self, iteration: uint) {
+        Spin.wait(iteration)
+    }
+
+    #[cfg(all(feature = "wait_backoff", not(feature = "wait_hook")))]
+    #[inline(always)]
+    fn wait(&self, iteration: uint) {
+        Backoff.wait(iteration)
+    }
+
+    #[cfg(feature = "wait_hook")]
+    #[inline(always)]
+    fn wait(&self, iteration: uint) {
+        unsafe { lazy_static_core_wait(iteration) }
+    }
+}
+
+#[cfg(feature = "wait_hook")]
+extern "Rust" {
+    fn lazy_static_core_wait(iteration: uint);
+}
+
+/// Tells the processor that the current thread is busy waiting.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline(always)]
+pub fn spin_loop_hint() {
+    unsafe { asm!("pause" :::: "volatile") }
+}
+
+/// Tells the processor that the current thread is busy waiting.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+#[inline(always)]
+pub fn spin_loop_hint() {}
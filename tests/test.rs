@@ -1,9 +1,20 @@
 #![feature(phase)]
 
 extern crate core;
-#[phase(plugin)]
+#[phase(plugin, link)]
 extern crate lazy_static_core;
+use lazy_static_core::{Lazy, LazyStatic, Mutex, Once, ONCE_INIT, OnceCell, RwLock};
+use lazy_static_core::{deref_timeout, InProgress};
+use lazy_static_core::timeout::{Clock, Deadline, Iterations};
+use lazy_static_core::unsync;
+use lazy_static_core::wait::{Backoff, Hook};
+use std::any::AnyRefExt;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::task;
+use std::io::timer;
+use std::time::Duration;
+use std::sync::atomic::{AtomicBool, INIT_ATOMIC_BOOL, AtomicUint, INIT_ATOMIC_UINT, Ordering};
 
 lazy_static_core! {
     static ref NUMBER: uint = times_two(3);
@@ -49,3 +60,801 @@ mod visibility {
 fn test_visibility() {
     assert_eq!(*visibility::FOO, box 0u);
 }
+
+static CONTENDED_INITS: AtomicUint = INIT_ATOMIC_UINT;
+
+fn slow_init() -> uint {
+    CONTENDED_INITS.fetch_add(1, Ordering::SeqCst);
+    // keep the initializer running long enough for the other threads to arrive
+    timer::sleep(Duration::milliseconds(50));
+    42
+}
+
+lazy_static_core! {
+    static ref CONTENDED: uint = slow_init();
+}
+
+#[test]
+fn test_multithreaded() {
+    let (tx, rx) = channel();
+    for _ in range(0u, 16) {
+        let tx = tx.clone();
+        spawn(proc() {
+            for _ in range(0u, 1000) {
+                assert_eq!(*CONTENDED, 42);
+            }
+            tx.send(());
+        });
+    }
+    for _ in range(0u, 16) {
+        rx.recv();
+    }
+    assert_eq!(CONTENDED_INITS.load(Ordering::SeqCst), 1);
+}
+
+fn panicking_init() -> uint {
+    panic!("initializer panicked")
+}
+
+lazy_static_core! {
+    static ref POISONED: uint = panicking_init();
+}
+
+#[test]
+fn test_poisoned() {
+    assert!(!POISONED.is_poisoned());
+    assert!(task::try(proc() *POISONED).is_err());
+    assert!(POISONED.is_poisoned());
+
+    let err = task::try(proc() *POISONED).unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert_eq!(msg.as_slice(), "lazy static POISONED was poisoned");
+}
+
+fn parse(s: &str) -> Result<uint, String> {
+    match from_str(s) {
+        Some(n) => Ok(n),
+        None => Err(format!("invalid number: {}", s)),
+    }
+}
+
+lazy_static_core! {
+    try static ref PARSED: Result<uint, String> = parse("42");
+    try static ref UNPARSABLE: Result<uint, String> = parse("forty-two");
+}
+
+#[test]
+fn test_try() {
+    assert_eq!(PARSED.try_get(), Ok(&42));
+    assert_eq!(*PARSED, 42);
+
+    assert_eq!(UNPARSABLE.try_get(), Err(&"invalid number: forty-two".to_string()));
+    assert_eq!(UNPARSABLE.try_get(), Err(&"invalid number: forty-two".to_string()));
+
+    let err = task::try(proc() *UNPARSABLE).unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert_eq!(msg.as_slice(), "lazy static UNPARSABLE failed to initialize: invalid number: forty-two");
+}
+
+static PROBES: AtomicUint = INIT_ATOMIC_UINT;
+
+fn probe() -> Option<uint> {
+    match PROBES.fetch_add(1, Ordering::SeqCst) {
+        0 | 1 => None,
+        _ => Some(7),
+    }
+}
+
+lazy_static_core! {
+    retry static ref DEVICE: uint = probe();
+}
+
+#[test]
+fn test_retry() {
+    assert!(task::try(proc() *DEVICE).is_err());
+    assert_eq!(DEVICE.try_get(), None);
+    assert_eq!(DEVICE.try_get(), Some(&7));
+    assert_eq!(*DEVICE, 7);
+    assert_eq!(DEVICE.try_get(), Some(&7));
+    assert_eq!(PROBES.load(Ordering::SeqCst), 3);
+}
+
+lazy_static_core! {
+    static ref CMDLINE: String;
+}
+
+#[test]
+fn test_late_init() {
+    assert_eq!(CMDLINE.try_get(), None);
+    let err = task::try(proc() CMDLINE.len()).unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert_eq!(msg.as_slice(), "lazy static CMDLINE was dereferenced before it was initialized");
+
+    assert_eq!(CMDLINE::init("quiet".to_string()), Ok(()));
+    assert_eq!(CMDLINE::init("verbose".to_string()), Err("verbose".to_string()));
+    assert_eq!(CMDLINE.as_slice(), "quiet");
+    assert_eq!(CMDLINE.try_get(), Some(&"quiet".to_string()));
+}
+
+lazy_static_core! {
+    static ref SEVEN: uint = 7;
+}
+
+#[test]
+fn test_free_functions_on_trait_objects() {
+    let seven: &'static LazyStatic<uint> = &SEVEN;
+    assert_eq!(lazy_static_core::get(seven), None);
+    lazy_static_core::initialize(seven);
+    assert_eq!(lazy_static_core::get(seven), Some(&7));
+    assert_eq!(lazy_static_core::try_deref(seven), Ok(&7));
+}
+
+lazy_static_core! {
+    /// The answer, documented.
+    #[allow(dead_code)]
+    pub static ref DOCUMENTED: uint = 42;
+    #[cfg(not(test))]
+    static ref GATED: &'static str = "release";
+    #[cfg(test)]
+    static ref GATED: &'static str = "test";
+}
+
+#[test]
+fn test_attributes() {
+    assert_eq!(*DOCUMENTED, 42);
+    assert_eq!(*GATED, "test");
+}
+
+lazy_static_core! {
+    static ref FIVE: uint = 5;
+    static ref SIX: uint = 6;
+}
+
+fn sum(lazies: &[&'static LazyStatic<uint>]) -> uint {
+    lazies.iter().map(|l| *l.force()).fold(0, |a, b| a + b)
+}
+
+#[test]
+fn test_lazy_static_trait() {
+    let five: &'static LazyStatic<uint> = &FIVE;
+    assert!(!five.is_initialized());
+    assert_eq!(five.get(), None);
+    assert_eq!(*five.force(), 5);
+    assert!(five.is_initialized());
+    assert_eq!(five.get(), Some(&5));
+
+    assert_eq!(sum(&[&FIVE as &LazyStatic<uint>, &SIX as &LazyStatic<uint>]), 11);
+    assert!(SIX.is_initialized());
+
+    assert_eq!(PARSED.force(), &42);
+    assert!(task::try(proc() UNPARSABLE.force()).is_err());
+    assert_eq!(UNPARSABLE.get(), None);
+}
+
+lazy_static_core! {
+    static ref EAGER: uint = 1;
+    static ref EAGER_TOO: uint = 2;
+}
+
+#[test]
+fn test_initialize() {
+    assert!(!EAGER.is_initialized());
+    lazy_static_core::initialize(&EAGER);
+    assert!(EAGER.is_initialized());
+
+    initialize_all!(EAGER_TOO, visibility::FOO);
+    assert!(EAGER_TOO.is_initialized());
+    assert!(visibility::FOO.is_initialized());
+}
+
+fn wait_until(flag: &AtomicBool) {
+    while !flag.load(Ordering::SeqCst) {
+        timer::sleep(Duration::milliseconds(1));
+    }
+}
+
+/// Signals `started`, then blocks until `released` is set.
+fn blocking_init(started: &AtomicBool, released: &AtomicBool) -> uint {
+    started.store(true, Ordering::SeqCst);
+    wait_until(released);
+    3
+}
+
+static PEEK_STARTED: AtomicBool = INIT_ATOMIC_BOOL;
+static PEEK_RELEASED: AtomicBool = INIT_ATOMIC_BOOL;
+
+lazy_static_core! {
+    static ref PEEKED: uint = blocking_init(&PEEK_STARTED, &PEEK_RELEASED);
+}
+
+// with `no_cas`, other threads can not observe an initializer in progress
+#[cfg(not(feature = "no_cas"))]
+#[test]
+fn test_get() {
+    assert_eq!(lazy_static_core::get(&PEEKED), None);
+
+    let (tx, rx) = channel();
+    spawn(proc() {
+        tx.send(*PEEKED);
+    });
+    wait_until(&PEEK_STARTED);
+    // the initializer is running on the other thread
+    assert_eq!(lazy_static_core::get(&PEEKED), None);
+
+    PEEK_RELEASED.store(true, Ordering::SeqCst);
+    assert_eq!(rx.recv(), 3);
+    assert_eq!(lazy_static_core::get(&PEEKED), Some(&3));
+}
+
+static BUSY_STARTED: AtomicBool = INIT_ATOMIC_BOOL;
+static BUSY_RELEASED: AtomicBool = INIT_ATOMIC_BOOL;
+
+lazy_static_core! {
+    static ref BUSY: uint = blocking_init(&BUSY_STARTED, &BUSY_RELEASED);
+    static ref IDLE: uint = 4;
+}
+
+// with `no_cas`, other threads can not observe an initializer in progress
+#[cfg(not(feature = "no_cas"))]
+#[test]
+fn test_try_deref() {
+    assert_eq!(lazy_static_core::try_deref(&IDLE), Ok(&4));
+
+    let (tx, rx) = channel();
+    spawn(proc() {
+        tx.send(*BUSY);
+    });
+    wait_until(&BUSY_STARTED);
+    assert_eq!(lazy_static_core::try_deref(&BUSY), Err(lazy_static_core::InProgress));
+
+    BUSY_RELEASED.store(true, Ordering::SeqCst);
+    assert_eq!(rx.recv(), 3);
+    assert_eq!(lazy_static_core::try_deref(&BUSY), Ok(&3));
+}
+
+static HOOK_STARTED: AtomicBool = INIT_ATOMIC_BOOL;
+static HOOK_RELEASED: AtomicBool = INIT_ATOMIC_BOOL;
+static HOOK_WAITS: AtomicUint = INIT_ATOMIC_UINT;
+
+fn release_on_wait(_: uint) {
+    HOOK_WAITS.fetch_add(1, Ordering::SeqCst);
+    HOOK_RELEASED.store(true, Ordering::SeqCst);
+}
+
+fn sleepy_init() -> uint {
+    timer::sleep(Duration::milliseconds(50));
+    42
+}
+
+lazy_static_core! {
+    static ref HOOKED: uint = blocking_init(&HOOK_STARTED, &HOOK_RELEASED)
+        with Hook(release_on_wait);
+    pub static ref BACKED_OFF: uint = sleepy_init() with Backoff;
+}
+
+// with `no_cas`, other threads can not observe an initializer in progress, and with `std`
+// or `futex` they sleep instead of running their strategy
+#[cfg(not(any(feature = "no_cas", feature = "std", feature = "futex")))]
+#[test]
+fn test_wait_strategy() {
+    let (tx, rx) = channel();
+    spawn(proc() {
+        tx.send(*HOOKED);
+    });
+    wait_until(&HOOK_STARTED);
+    // waiting on the initializer calls the hook, which releases it
+    assert_eq!(*HOOKED, 3);
+    assert_eq!(rx.recv(), 3);
+    assert!(HOOK_WAITS.load(Ordering::SeqCst) > 0);
+
+    let (tx, rx) = channel();
+    for _ in range(0u, 4) {
+        let tx = tx.clone();
+        spawn(proc() {
+            tx.send(*BACKED_OFF);
+        });
+    }
+    for _ in range(0u, 4) {
+        assert_eq!(rx.recv(), 42);
+    }
+}
+
+static STUCK_STARTED: AtomicBool = INIT_ATOMIC_BOOL;
+static STUCK_RELEASED: AtomicBool = INIT_ATOMIC_BOOL;
+
+lazy_static_core! {
+    static ref STUCK: uint = blocking_init(&STUCK_STARTED, &STUCK_RELEASED);
+}
+
+/// A clock that advances by one tick every time it is read.
+struct TickingClock {
+    ticks: Cell<u64>,
+}
+
+impl Clock for TickingClock {
+    fn now(&self) -> u64 {
+        self.ticks.set(self.ticks.get() + 1);
+        self.ticks.get()
+    }
+}
+
+// with `no_cas`, other threads can not observe an initializer in progress
+#[cfg(not(feature = "no_cas"))]
+#[test]
+fn test_deref_timeout() {
+    let (tx, rx) = channel();
+    spawn(proc() {
+        tx.send(*STUCK);
+    });
+    wait_until(&STUCK_STARTED);
+    assert_eq!(deref_timeout(&STUCK, Iterations(100)), Err(InProgress));
+    let clock = TickingClock { ticks: Cell::new(0) };
+    assert_eq!(deref_timeout(&STUCK, Deadline::after(&clock, 10)), Err(InProgress));
+    assert_eq!(clock.ticks.get(), 11);
+
+    STUCK_RELEASED.store(true, Ordering::SeqCst);
+    assert_eq!(rx.recv(), 3);
+    assert_eq!(deref_timeout(&STUCK, Iterations(0)), Ok(&3));
+}
+
+static TIMED_STARTED: AtomicBool = INIT_ATOMIC_BOOL;
+static TIMED_RELEASED: AtomicBool = INIT_ATOMIC_BOOL;
+static TIMED_WAITS: AtomicUint = INIT_ATOMIC_UINT;
+
+fn count_timed_waits(_: uint) {
+    TIMED_WAITS.fetch_add(1, Ordering::SeqCst);
+}
+
+lazy_static_core! {
+    static ref TIMED: uint = blocking_init(&TIMED_STARTED, &TIMED_RELEASED)
+        with Hook(count_timed_waits);
+}
+
+// with `no_cas`, other threads can not observe an initializer in progress, and with `std`
+// or `futex` they sleep instead of running their strategy
+#[cfg(not(any(feature = "no_cas", feature = "std", feature = "futex")))]
+#[test]
+fn test_deref_timeout_uses_strategy() {
+    let (tx, rx) = channel();
+    spawn(proc() {
+        tx.send(*TIMED);
+    });
+    wait_until(&TIMED_STARTED);
+    assert_eq!(deref_timeout(&TIMED, Iterations(5)), Err(InProgress));
+    assert_eq!(TIMED_WAITS.load(Ordering::SeqCst), 5);
+
+    TIMED_RELEASED.store(true, Ordering::SeqCst);
+    assert_eq!(rx.recv(), 3);
+}
+
+static PARK_STARTED: AtomicBool = INIT_ATOMIC_BOOL;
+static PARK_RELEASED: AtomicBool = INIT_ATOMIC_BOOL;
+static PARK_RUNS: AtomicUint = INIT_ATOMIC_UINT;
+
+fn parked_init() -> uint {
+    PARK_RUNS.fetch_add(1, Ordering::SeqCst);
+    blocking_init(&PARK_STARTED, &PARK_RELEASED)
+}
+
+lazy_static_core! {
+    static ref PARKED: uint = parked_init();
+}
+
+// with `watchdog`, sleeping threads wake up every millisecond on their own, which would hide
+// missing wakeups
+#[cfg(all(any(feature = "std", feature = "futex"), not(feature = "watchdog")))]
+#[test]
+fn test_sleeping_wait() {
+    let (tx, rx) = channel();
+    for _ in range(0u, 4) {
+        let tx = tx.clone();
+        spawn(proc() {
+            tx.send(*PARKED);
+        });
+    }
+    wait_until(&PARK_STARTED);
+    // give the other threads time to fall asleep
+    timer::sleep(Duration::milliseconds(50));
+    PARK_RELEASED.store(true, Ordering::SeqCst);
+    // the threads sleep without a timeout, so they only return if the initializer wakes them
+    let mut returned = 0u;
+    for _ in range(0u, 1000) {
+        match rx.try_recv() {
+            Ok(value) => {
+                assert_eq!(value, 3);
+                returned += 1;
+                if returned == 4 {
+                    break;
+                }
+            }
+            Err(_) => timer::sleep(Duration::milliseconds(1)),
+        }
+    }
+    assert_eq!(returned, 4);
+    assert_eq!(PARK_RUNS.load(Ordering::SeqCst), 1);
+}
+
+static LAZY_CALLS: AtomicUint = INIT_ATOMIC_UINT;
+
+fn counted_numbers() -> Vec<uint> {
+    LAZY_CALLS.fetch_add(1, Ordering::SeqCst);
+    vec![1, 2, 3]
+}
+
+static LAZY_NUMBERS: Lazy<Vec<uint>> = lazy!(counted_numbers);
+
+#[test]
+fn test_lazy_static_item() {
+    assert_eq!(LAZY_NUMBERS.get(), None);
+    assert_eq!(LAZY_NUMBERS.as_slice(), [1, 2, 3].as_slice());
+    assert_eq!(*LAZY_NUMBERS, vec![1, 2, 3]);
+    assert_eq!(LAZY_NUMBERS.try_force(), Ok(&vec![1, 2, 3]));
+    assert_eq!(LAZY_CALLS.load(Ordering::SeqCst), 1);
+}
+
+fn greeting() -> String {
+    "hello".to_string()
+}
+
+struct Greeter {
+    greeting: Lazy<String>,
+}
+
+#[test]
+fn test_lazy_field() {
+    let greeter = Greeter { greeting: Lazy::new(greeting as fn() -> String) };
+    assert_eq!(greeter.greeting.get(), None);
+    assert_eq!(greeter.greeting.as_slice(), "hello");
+    assert_eq!(greeter.greeting.get(), Some(&"hello".to_string()));
+}
+
+static SETUP: Once = ONCE_INIT;
+static SETUP_RUNS: AtomicUint = INIT_ATOMIC_UINT;
+
+#[test]
+fn test_once() {
+    assert!(!SETUP.is_completed());
+    for _ in range(0u, 3) {
+        SETUP.call_once(|| { SETUP_RUNS.fetch_add(1, Ordering::SeqCst); });
+    }
+    assert!(SETUP.is_completed());
+    assert_eq!(SETUP_RUNS.load(Ordering::SeqCst), 1);
+}
+
+static CONFIG: OnceCell<uint> = once_cell!();
+
+#[test]
+fn test_once_cell_static() {
+    assert_eq!(CONFIG.get(), None);
+    assert_eq!(CONFIG.set(42), Ok(()));
+    assert_eq!(CONFIG.set(43), Err(43));
+    assert_eq!(CONFIG.get_or_init(|| 44), &42);
+    assert_eq!(CONFIG.get(), Some(&42));
+}
+
+#[test]
+fn test_once_cell_field() {
+    let cell = OnceCell::new();
+    assert_eq!(cell.get_or_init(|| "hello".to_string()).as_slice(), "hello");
+    assert_eq!(cell.set("bye".to_string()), Err("bye".to_string()));
+    assert!(!cell.is_poisoned());
+}
+
+lazy_static_core! {
+    static ref mut LOG: Vec<uint> = Vec::new();
+    static ref mut TICKS: Cell<uint> = Cell::new(0);
+}
+
+#[test]
+fn test_static_ref_mut() {
+    let (tx, rx) = channel();
+    for i in range(0u, 4) {
+        let tx = tx.clone();
+        spawn(proc() {
+            LOG.lock().push(i);
+            let ticks = TICKS.lock();
+            ticks.set(ticks.get() + 1);
+            tx.send(());
+        });
+    }
+    for _ in range(0u, 4) {
+        rx.recv();
+    }
+    let mut log = LOG.lock().clone();
+    log.sort();
+    assert_eq!(log, vec![0, 1, 2, 3]);
+    assert_eq!(TICKS.lock().get(), 4);
+}
+
+static TOTAL: Mutex<uint> = mutex!(0);
+
+#[test]
+fn test_mutex() {
+    {
+        let mut total = TOTAL.lock();
+        *total += 5;
+        assert!(TOTAL.try_lock().is_none());
+    }
+    assert_eq!(*TOTAL.try_lock().unwrap(), 5);
+}
+
+#[test]
+fn test_rwlock() {
+    let lock = RwLock::new(vec![1u]);
+    {
+        let first = lock.read();
+        let second = lock.try_read().unwrap();
+        assert_eq!(first.len() + second.len(), 2);
+        assert!(lock.try_write().is_none());
+    }
+    lock.write().push(2);
+    assert!(lock.try_write().is_some());
+    assert_eq!(*lock.read(), vec![1, 2]);
+}
+
+fn empty_list() -> RefCell<Vec<uint>> {
+    RefCell::new(Vec::new())
+}
+
+#[test]
+fn test_unsync_lazy() {
+    let list = unsync::Lazy::new(empty_list as fn() -> RefCell<Vec<uint>>);
+    assert!(list.get().is_none());
+    list.borrow_mut().push(1);
+    list.borrow_mut().push(2);
+    assert_eq!(*list.borrow(), vec![1, 2]);
+    assert_eq!(list.try_force().ok().unwrap().borrow().len(), 2);
+    assert!(!list.is_poisoned());
+}
+
+#[test]
+fn test_unsync_once_cell() {
+    let cell = unsync::OnceCell::new();
+    assert!(cell.get().is_none());
+    assert_eq!(cell.get_or_init(|| Cell::new(1)).get(), 1);
+    assert!(cell.set(Cell::new(2)).is_err());
+    cell.get().unwrap().set(3);
+    assert_eq!(cell.get_or_init(|| Cell::new(4)).get(), 3);
+    assert!(!cell.is_poisoned());
+}
+
+#[cfg(feature = "wait_hook")]
+mod wait_hook {
+    use lazy_static_core::wait::spin_loop_hint;
+    use std::sync::atomic::{AtomicBool, INIT_ATOMIC_BOOL, AtomicUint, INIT_ATOMIC_UINT,
+                            Ordering};
+    use super::{blocking_init, wait_until};
+
+    static WAITS: AtomicUint = INIT_ATOMIC_UINT;
+    static SLOW_STARTED: AtomicBool = INIT_ATOMIC_BOOL;
+    static SLOW_RELEASED: AtomicBool = INIT_ATOMIC_BOOL;
+
+    /// Spins like `wait::Spin`, but counts the calls and releases `SLOW`.
+    fn count_waits(_: uint) {
+        WAITS.fetch_add(1, Ordering::SeqCst);
+        SLOW_RELEASED.store(true, Ordering::SeqCst);
+        spin_loop_hint();
+    }
+
+    lazy_static_wait_hook!(count_waits);
+
+    lazy_static_core! {
+        static ref SLOW: uint = blocking_init(&SLOW_STARTED, &SLOW_RELEASED);
+    }
+
+    // with `no_cas`, other threads can not observe an initializer in progress, and with
+    // `std` or `futex` they sleep instead of running their strategy
+    #[cfg(not(any(feature = "no_cas", feature = "std", feature = "futex")))]
+    #[test]
+    fn test_default_strategy_calls_hook() {
+        let (tx, rx) = channel();
+        spawn(proc() {
+            tx.send(*SLOW);
+        });
+        wait_until(&SLOW_STARTED);
+        // waiting with the default strategy calls the hook, which releases the initializer
+        assert_eq!(*SLOW, 3);
+        assert_eq!(rx.recv(), 3);
+        assert!(WAITS.load(Ordering::SeqCst) > 0);
+    }
+}
+
+#[cfg(feature = "watchdog")]
+mod watchdog {
+    use std::sync::atomic::{AtomicBool, INIT_ATOMIC_BOOL, AtomicUint, INIT_ATOMIC_UINT,
+                            Ordering};
+    use super::{blocking_init, wait_until};
+
+    static REPORTS: AtomicUint = INIT_ATOMIC_UINT;
+    static HUNG_STARTED: AtomicBool = INIT_ATOMIC_BOOL;
+    static HUNG_RELEASED: AtomicBool = INIT_ATOMIC_BOOL;
+
+    fn report(what: &'static str, iterations: uint) {
+        assert_eq!(iterations % 64, 0);
+        if what == "lazy static HUNG" {
+            REPORTS.fetch_add(1, Ordering::SeqCst);
+            HUNG_RELEASED.store(true, Ordering::SeqCst);
+        }
+    }
+
+    lazy_static_watchdog!(64, report);
+
+    lazy_static_core! {
+        static ref HUNG: uint = blocking_init(&HUNG_STARTED, &HUNG_RELEASED);
+    }
+
+    // with `no_cas`, other threads can not observe an initializer in progress, and with
+    // `std` or `futex` they sleep instead of counting iterations
+    #[cfg(not(any(feature = "no_cas", feature = "std", feature = "futex")))]
+    #[test]
+    fn test_watchdog() {
+        let (tx, rx) = channel();
+        spawn(proc() {
+            tx.send(*HUNG);
+        });
+        wait_until(&HUNG_STARTED);
+        // the watchdog reports this thread waiting and releases the initializer
+        assert_eq!(*HUNG, 3);
+        assert_eq!(rx.recv(), 3);
+        assert!(REPORTS.load(Ordering::SeqCst) > 0);
+    }
+}
+
+#[cfg(any(feature = "critical_section", feature = "thread_id"))]
+mod thread_id {
+    thread_local!(static MARKER: u8 = 0);
+
+    /// Returns an id that differs between all running threads.
+    pub fn current_thread() -> uint {
+        MARKER.with(|marker| marker as *const u8 as uint)
+    }
+
+    #[cfg(feature = "thread_id")]
+    mod hook {
+        use super::current_thread;
+
+        lazy_static_thread_id!(current_thread());
+    }
+}
+
+lazy_static_core! {
+    static ref SELF_REFERENTIAL: uint = *SELF_REFERENTIAL + 1;
+}
+
+#[cfg(any(feature = "std", feature = "thread_id"))]
+#[test]
+fn test_recursion_detected() {
+    let err = task::try(proc() *SELF_REFERENTIAL).unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert_eq!(msg.as_slice(), "lazy static SELF_REFERENTIAL was initialized recursively");
+    assert!(SELF_REFERENTIAL.is_poisoned());
+}
+
+fn peek_at_itself() -> uint {
+    // an access that doesn't wait fails instead of panicking, like one from an interrupt
+    // handler that has interrupted the initializer
+    assert_eq!(lazy_static_core::try_deref(&PEEKS_AT_ITSELF), Err(InProgress));
+    assert_eq!(lazy_static_core::get(&PEEKS_AT_ITSELF), None);
+    5
+}
+
+lazy_static_core! {
+    static ref PEEKS_AT_ITSELF: uint = peek_at_itself();
+}
+
+#[test]
+fn test_try_deref_from_own_initializer() {
+    assert_eq!(*PEEKS_AT_ITSELF, 5);
+}
+
+// without thread identity, initializers of other tests that run at the same time show up
+// in the graph
+#[cfg(all(feature = "trace", any(feature = "std", feature = "thread_id")))]
+mod trace {
+    use lazy_static_core::trace::write_dot;
+    use std::fmt::FormatWriter;
+    use std::fmt;
+
+    lazy_static_core! {
+        static ref ROOT: uint = *BRANCH + *LEAF;
+        static ref BRANCH: uint = *LEAF * 2;
+        static ref LEAF: uint = 1;
+    }
+
+    struct Dot(String);
+
+    impl FormatWriter for Dot {
+        fn write(&mut self, bytes: &[u8]) -> fmt::Result {
+            let Dot(ref mut dot) = *self;
+            dot.push_str(::std::str::from_utf8(bytes).unwrap());
+            Ok(())
+        }
+    }
+
+    /// Returns the id of the node labeled `label` in `dot`.
+    fn node<'a>(dot: &'a str, label: &str) -> &'a str {
+        let line = dot.lines().find(|line| line.contains(label)).unwrap();
+        line.trim().split(' ').next().unwrap()
+    }
+
+    #[test]
+    fn test_trace() {
+        assert_eq!(*ROOT, 3);
+        let mut dot = Dot(String::new());
+        write_dot(&mut dot).unwrap();
+        let Dot(dot) = dot;
+        let dot = dot.as_slice();
+
+        assert!(dot.starts_with("digraph lazy_statics {"));
+        // the threads of other tests can keep the graph locked for too long, in which case
+        // some of the accesses below may not have been recorded
+        if dot.contains("found the graph locked") {
+            return;
+        }
+        let root = node(dot, "\"lazy static ROOT (#");
+        let branch = node(dot, "\"lazy static BRANCH (#");
+        let leaf = node(dot, "\"lazy static LEAF (#");
+        assert!(dot.contains(format!("{} -> {};", root, branch).as_slice()));
+        assert!(dot.contains(format!("{} -> {};", root, leaf).as_slice()));
+        assert!(dot.contains(format!("{} -> {};", branch, leaf).as_slice()));
+        assert!(!dot.contains(format!("{} -> {};", leaf, root).as_slice()));
+    }
+}
+
+#[cfg(feature = "critical_section")]
+mod critical_section {
+    use lazy_static_core::critical_section::CriticalSection;
+    use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, Ordering};
+    use thread_id::current_thread;
+
+    static OWNER: AtomicUint = INIT_ATOMIC_UINT;
+
+    /// A reentrant global lock, which excludes all other threads like disabling interrupts
+    /// excludes all other code on a single core system.
+    struct GlobalLock;
+
+    impl CriticalSection for GlobalLock {
+        fn acquire(&self) -> uint {
+            let me = current_thread();
+            if OWNER.load(Ordering::SeqCst) == me {
+                return 1;
+            }
+            while OWNER.compare_and_swap(0, me, Ordering::SeqCst) != 0 {}
+            0
+        }
+
+        fn release(&self, token: uint) {
+            if token == 0 {
+                OWNER.store(0, Ordering::SeqCst);
+            }
+        }
+    }
+
+    lazy_static_critical_section!(GlobalLock);
+
+    lazy_static_core! {
+        static ref HELD_DURING_INIT: bool = OWNER.load(Ordering::SeqCst) == current_thread();
+    }
+
+    #[test]
+    fn test_initializer_runs_in_critical_section() {
+        assert!(*HELD_DURING_INIT);
+    }
+
+    #[cfg(feature = "no_cas")]
+    mod no_cas {
+        use std::any::AnyRefExt;
+        use std::task;
+
+        lazy_static_core! {
+            static ref RECURSIVE: uint = *RECURSIVE + 1;
+        }
+
+        #[test]
+        fn test_recursive_initialization() {
+            let err = task::try(proc() *RECURSIVE).unwrap_err();
+            let msg = err.downcast_ref::<String>().unwrap();
+            assert_eq!(msg.as_slice(), "lazy static RECURSIVE was initialized recursively");
+        }
+    }
+}